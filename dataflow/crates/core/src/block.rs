@@ -1,6 +1,6 @@
-use std::{marker::PhantomData, ops::Deref};
+use std::marker::PhantomData;
 use const_default::ConstDefault;
-use super::system::{SystemStorage};
+use super::system::{SystemStorage, SystemStorageItemId};
 
 /** Traits */
 pub trait ReadAccess<T> {
@@ -26,13 +26,15 @@ pub trait Initial<T> {
 pub struct Parameter<'a, T: Copy> {
   storage: &'a dyn SystemStorage,
   id: usize,
+  /// Value passed to `Initial::init`, see `Reset`
+  initial: Option<T>,
   _marker: PhantomData<&'a T>,
 }
 
 impl<'a, T: Copy> Parameter<'a, T> {  
   pub const fn new(storage: &'a dyn SystemStorage, id: usize) -> Parameter<'a, T> {
     Parameter::<'a, T> { 
-      storage: storage, id: id, _marker: PhantomData {}
+      storage: storage, id: id, initial: None, _marker: PhantomData {}
     }
   }
   pub fn id(&self) -> usize {
@@ -42,7 +44,7 @@ impl<'a, T: Copy> Parameter<'a, T> {
 
 impl<'a> Access<f64> for Parameter<'a, f64> {
   fn get(&self) -> f64 {
-    *self.storage.r_param_get(self.id)
+    self.storage.r_param_get(self.id)
   }
   fn set(&self, v: f64) {
     self.storage.r_param_set(self.id, v)
@@ -51,39 +53,42 @@ impl<'a> Access<f64> for Parameter<'a, f64> {
 
 impl<'a> Initial<f64> for Parameter<'a, f64> {
   fn init(mut self, v: f64) -> Self {
-    Access::set(&mut self, v);
+    self.initial = Some(v);
+    Access::set(&self, v);
     self
   }
 }
 
-impl<'a> Deref for Parameter<'a, f64> {
-  type Target = f64;
-
-  fn deref(&self) -> &Self::Target {
-    self.storage.r_param_get(self.id)
-  }
-}
-
 impl<'a> Access<bool> for Parameter<'a, bool> {
   fn get(&self) -> bool {
-    *self.storage.b_param_get(self.id)
+    self.storage.b_param_get(self.id)
   }
   fn set(&self, v: bool) {
     self.storage.b_param_set(self.id, v)
   }
 }
 
-impl<'a> Deref for Parameter<'a, bool> {
-  type Target = bool;
+impl<'a> Initial<bool> for Parameter<'a, bool> {
+  fn init(mut self, v: bool) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
+    self
+  }
+}
 
-  fn deref(&self) -> &Self::Target {
-    self.storage.b_param_get(self.id)
+impl<'a> Access<i64> for Parameter<'a, i64> {
+  fn get(&self) -> i64 {
+    self.storage.i_param_get(self.id)
+  }
+  fn set(&self, v: i64) {
+    self.storage.i_param_set(self.id, v)
   }
 }
 
-impl<'a> Initial<bool> for Parameter<'a, bool> {
-  fn init(mut self, v: bool) -> Self {
-    Access::set(&mut self, v);
+impl<'a> Initial<i64> for Parameter<'a, i64> {
+  fn init(mut self, v: i64) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
     self
   }
 }
@@ -107,31 +112,39 @@ impl<'a, T: Copy> Input<'a, T> {
   } 
 }
 
-impl<'a> ReadAccess<f64> for Input<'a, f64> {
-  fn get(&self) -> f64 {
-    *self.storage.r_out_get(self.output_id.unwrap())
+impl<'a> Input<'a, f64> {
+  pub fn source(&self) -> Option<SystemStorageItemId> {
+    self.output_id.map(SystemStorageItemId::RealOutput)
   }
 }
 
-impl<'a> Deref for Input<'a, f64> {
-  type Target = f64;
+impl<'a> Input<'a, bool> {
+  pub fn source(&self) -> Option<SystemStorageItemId> {
+    self.output_id.map(SystemStorageItemId::BoolOutput)
+  }
+}
 
-  fn deref(&self) -> &Self::Target {
-    self.storage.r_out_get(self.output_id.unwrap())
+impl<'a> Input<'a, i64> {
+  pub fn source(&self) -> Option<SystemStorageItemId> {
+    self.output_id.map(SystemStorageItemId::IntOutput)
+  }
+}
+
+impl<'a> ReadAccess<f64> for Input<'a, f64> {
+  fn get(&self) -> f64 {
+    self.storage.r_out_get(self.output_id.expect("Input is not connected, see ISystem::validate"))
   }
 }
 
 impl<'a> ReadAccess<bool> for Input<'a, bool> {
   fn get(&self) -> bool {
-    *self.storage.b_out_get(self.output_id.unwrap())
+    self.storage.b_out_get(self.output_id.expect("Input is not connected, see ISystem::validate"))
   }
 }
 
-impl<'a> Deref for Input<'a, bool> {
-  type Target = bool;
-
-  fn deref(&self) -> &Self::Target {
-    self.storage.b_out_get(self.output_id.unwrap())
+impl<'a> ReadAccess<i64> for Input<'a, i64> {
+  fn get(&self) -> i64 {
+    self.storage.i_out_get(self.output_id.expect("Input is not connected, see ISystem::validate"))
   }
 }
 
@@ -154,7 +167,7 @@ impl<'a, T: Copy> Output<'a, T> {
 
 impl<'a> Access<f64> for Output<'a, f64> {
   fn get(&self) -> f64 {
-    *self.storage.r_out_get(self.id)
+    self.storage.r_out_get(self.id)
   }
   fn set(&self, v: f64) {
     self.storage.r_out_set(self.id, v)
@@ -163,26 +176,37 @@ impl<'a> Access<f64> for Output<'a, f64> {
 
 impl<'a> Access<bool> for Output<'a, bool> {
   fn get(&self) -> bool {
-    *self.storage.b_out_get(self.id)
+    self.storage.b_out_get(self.id)
   }
   fn set(&self, v: bool) {
     self.storage.b_out_set(self.id, v)
   }
 }
 
+impl<'a> Access<i64> for Output<'a, i64> {
+  fn get(&self) -> i64 {
+    self.storage.i_out_get(self.id)
+  }
+  fn set(&self, v: i64) {
+    self.storage.i_out_set(self.id, v)
+  }
+}
+
 
 
 /** States */
 pub struct DiscreteState<'a, T: Copy> {
   storage: &'a dyn SystemStorage,
   id: usize,
+  /// Value passed to `Initial::init`, see `Reset`
+  initial: Option<T>,
   _marker: PhantomData<&'a T>
 }
 
 impl<'a, T: Copy> DiscreteState<'a, T> {  
   pub const fn new(storage: &'a dyn SystemStorage, id: usize) -> DiscreteState<'a, T> {
     DiscreteState::<'a, T> { 
-      storage: storage, id: id, _marker:  PhantomData {}
+      storage: storage, id: id, initial: None, _marker:  PhantomData {}
     }
   }
   pub fn id(&self) -> usize {
@@ -190,27 +214,53 @@ impl<'a, T: Copy> DiscreteState<'a, T> {
   }
 }
 
+impl<'a> Access<f64> for DiscreteState<'a, f64> {
+  fn get(&self) -> f64 {
+    self.storage.r_dstate_get(self.id)
+  }
+  fn set(&self, v: f64) {
+    self.storage.r_dstate_set(self.id, v)
+  }
+}
+
+impl<'a> Initial<f64> for DiscreteState<'a, f64> {
+  fn init(mut self, v: f64) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
+    self
+  }
+}
+
 impl<'a> Access<bool> for DiscreteState<'a, bool> {
   fn get(&self) -> bool {
-    *self.storage.b_state_get(self.id)
+    self.storage.b_state_get(self.id)
   }
   fn set(&self, v: bool) {
     self.storage.b_state_set(self.id, v)
   }
 }
 
-impl<'a> Deref for DiscreteState<'a, bool> {
-  type Target = bool;
-
-  fn deref(&self) -> &Self::Target {
-    self.storage.b_state_get(self.id)
+impl<'a> Initial<bool> for DiscreteState<'a, bool> {
+  fn init(mut self, v: bool) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
+    self
   }
 }
 
+impl<'a> Access<i64> for DiscreteState<'a, i64> {
+  fn get(&self) -> i64 {
+    self.storage.i_state_get(self.id)
+  }
+  fn set(&self, v: i64) {
+    self.storage.i_state_set(self.id, v)
+  }
+}
 
-impl<'a> Initial<bool> for DiscreteState<'a, bool> {
-  fn init(mut self, v: bool) -> Self {
-    Access::set(&mut self, v);
+impl<'a> Initial<i64> for DiscreteState<'a, i64> {
+  fn init(mut self, v: i64) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
     self
   }
 }
@@ -218,13 +268,15 @@ impl<'a> Initial<bool> for DiscreteState<'a, bool> {
 pub struct ContinuousState<'a, T: Copy> {
   storage: &'a dyn SystemStorage,
   id: usize,
+  /// Value passed to `Initial::init`, see `Reset`
+  initial: Option<T>,
   _marker: PhantomData<&'a T>
 }
 
 impl<'a, T: Copy> ContinuousState<'a, T> {  
   pub const fn new(storage: &'a dyn SystemStorage, id: usize) -> ContinuousState<'a, T> {
     ContinuousState::<'a, T> { 
-      storage: storage, id: id, _marker:  PhantomData {}
+      storage: storage, id: id, initial: None, _marker:  PhantomData {}
     }
   }
   pub fn id(&self) -> usize {
@@ -234,24 +286,16 @@ impl<'a, T: Copy> ContinuousState<'a, T> {
 
 impl<'a> Access<f64> for ContinuousState<'a, f64> {
   fn get(&self) -> f64 {
-    *self.storage.r_state_get(self.id)
+    self.storage.r_state_get(self.id)
   }
   fn set(&self, v: f64) {
     self.storage.r_state_set(self.id, v)
   }
 }
 
-impl<'a> Deref for ContinuousState<'a, f64> {
-  type Target = f64;
-
-  fn deref(&self) -> &Self::Target {
-    self.storage.r_state_get(self.id)
-  }
-}
-
 impl<'a> DerivativeAccess<f64> for ContinuousState<'a, f64> {
   fn der_get(&self) -> f64 {
-    *self.storage.r_state_der_get(self.id)
+    self.storage.r_state_der_get(self.id)
   }
   fn der_set(&self, v: f64) {
     self.storage.r_state_der_set(self.id, v)
@@ -260,7 +304,165 @@ impl<'a> DerivativeAccess<f64> for ContinuousState<'a, f64> {
 
 impl<'a> Initial<f64> for ContinuousState<'a, f64> {
   fn init(mut self, v: f64) -> Self {
-    Access::set(&mut self, v);
+    self.initial = Some(v);
+    Access::set(&self, v);
+    self
+  }
+}
+
+
+/** Arrays */
+/// Fixed-size real signal, stored element by element in consecutive slots of a real lane.
+/// Matrices are stored row by row.
+pub trait RealArray: Copy {
+  const LEN: usize;
+  fn from_fn<F: FnMut(usize) -> f64>(f: F) -> Self;
+  fn element(&self, i: usize) -> f64;
+}
+
+impl<const N: usize> RealArray for [f64; N] {
+  const LEN: usize = N;
+
+  fn from_fn<F: FnMut(usize) -> f64>(f: F) -> Self {
+    std::array::from_fn(f)
+  }
+  fn element(&self, i: usize) -> f64 {
+    self[i]
+  }
+}
+
+impl<const R: usize, const C: usize> RealArray for [[f64; C]; R] {
+  const LEN: usize = R * C;
+
+  fn from_fn<F: FnMut(usize) -> f64>(mut f: F) -> Self {
+    std::array::from_fn(|r| std::array::from_fn(|c| f(r * C + c)))
+  }
+  fn element(&self, i: usize) -> f64 {
+    self[i / C][i % C]
+  }
+}
+
+impl<'a, T: RealArray> Parameter<'a, T> {
+  /// Storage items of the elements, e.g. to write one column per element
+  pub fn items(&self) -> Vec<SystemStorageItemId> {
+    (self.id..self.id + T::LEN).map(SystemStorageItemId::RealParameter).collect()
+  }
+}
+
+impl<'a, T: RealArray> Access<T> for Parameter<'a, T> {
+  fn get(&self) -> T {
+    T::from_fn(|i| self.storage.r_param_get(self.id + i))
+  }
+  fn set(&self, v: T) {
+    for i in 0..T::LEN {
+      self.storage.r_param_set(self.id + i, v.element(i))
+    }
+  }
+}
+
+impl<'a, T: RealArray> Initial<T> for Parameter<'a, T> {
+  fn init(mut self, v: T) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
+    self
+  }
+}
+
+impl<'a, T: RealArray> Input<'a, T> {
+  pub fn source(&self) -> Option<SystemStorageItemId> {
+    self.output_id.map(SystemStorageItemId::RealOutput)
+  }
+}
+
+impl<'a, T: RealArray> ReadAccess<T> for Input<'a, T> {
+  fn get(&self) -> T {
+    let id = self.output_id.expect("Input is not connected, see ISystem::validate");
+    T::from_fn(|i| self.storage.r_out_get(id + i))
+  }
+}
+
+impl<'a, T: RealArray> Output<'a, T> {
+  pub fn items(&self) -> Vec<SystemStorageItemId> {
+    (self.id..self.id + T::LEN).map(SystemStorageItemId::RealOutput).collect()
+  }
+}
+
+impl<'a, T: RealArray> Access<T> for Output<'a, T> {
+  fn get(&self) -> T {
+    T::from_fn(|i| self.storage.r_out_get(self.id + i))
+  }
+  fn set(&self, v: T) {
+    for i in 0..T::LEN {
+      self.storage.r_out_set(self.id + i, v.element(i))
+    }
+  }
+}
+
+// Written per shape rather than for any `RealArray`, so as not to overlap with `StateEnum`
+macro_rules! dstate_array_impl {
+  ($($n: ident),*; $ty: ty) => {
+    impl<'a, $(const $n: usize),*> DiscreteState<'a, $ty> {
+      pub fn items(&self) -> Vec<SystemStorageItemId> {
+        (self.id..self.id + <$ty as RealArray>::LEN).map(SystemStorageItemId::RealDiscreteState).collect()
+      }
+    }
+
+    impl<'a, $(const $n: usize),*> Access<$ty> for DiscreteState<'a, $ty> {
+      fn get(&self) -> $ty {
+        <$ty as RealArray>::from_fn(|i| self.storage.r_dstate_get(self.id + i))
+      }
+      fn set(&self, v: $ty) {
+        for i in 0..<$ty as RealArray>::LEN {
+          self.storage.r_dstate_set(self.id + i, v.element(i))
+        }
+      }
+    }
+
+    impl<'a, $(const $n: usize),*> Initial<$ty> for DiscreteState<'a, $ty> {
+      fn init(mut self, v: $ty) -> Self {
+        self.initial = Some(v);
+        Access::set(&self, v);
+        self
+      }
+    }
+  };
+}
+
+dstate_array_impl!(N; [f64; N]);
+dstate_array_impl!(R, C; [[f64; C]; R]);
+
+impl<'a, T: RealArray> ContinuousState<'a, T> {
+  pub fn items(&self) -> Vec<SystemStorageItemId> {
+    (self.id..self.id + T::LEN).map(SystemStorageItemId::RealState).collect()
+  }
+}
+
+impl<'a, T: RealArray> Access<T> for ContinuousState<'a, T> {
+  fn get(&self) -> T {
+    T::from_fn(|i| self.storage.r_state_get(self.id + i))
+  }
+  fn set(&self, v: T) {
+    for i in 0..T::LEN {
+      self.storage.r_state_set(self.id + i, v.element(i))
+    }
+  }
+}
+
+impl<'a, T: RealArray> DerivativeAccess<T> for ContinuousState<'a, T> {
+  fn der_get(&self) -> T {
+    T::from_fn(|i| self.storage.r_state_der_get(self.id + i))
+  }
+  fn der_set(&self, v: T) {
+    for i in 0..T::LEN {
+      self.storage.r_state_der_set(self.id + i, v.element(i))
+    }
+  }
+}
+
+impl<'a, T: RealArray> Initial<T> for ContinuousState<'a, T> {
+  fn init(mut self, v: T) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
     self
   }
 }
@@ -270,15 +472,20 @@ impl<'a> Initial<f64> for ContinuousState<'a, f64> {
 pub struct BlockSize {
   pub r_param: usize,
   pub b_param: usize,
+  pub i_param: usize,
 
   pub r_state: usize,
+  pub r_dstate: usize,
   pub b_state: usize,
+  pub i_state: usize,
 
   pub r_in: usize,
   pub b_in: usize,
+  pub i_in: usize,
 
   pub r_out: usize,
   pub b_out: usize,
+  pub i_out: usize,
 }
 
 impl BlockSize {
@@ -327,6 +534,251 @@ pub enum UpdateComputation<'a> {
   Output(OutputUpdateFunction<'a>)
 }
 
+/** Enums */
+/// Finite set of values of a discrete state, e.g. the modes of a state machine.
+/// Stored in the integer lane.
+pub trait StateEnum: Copy {
+  fn to_i64(self) -> i64;
+  fn from_i64(v: i64) -> Option<Self>;
+  /// Name of the variant with the given integer value
+  fn variant_name(v: i64) -> Option<&'static str>;
+}
+
+impl<'a, E: StateEnum> Access<E> for DiscreteState<'a, E> {
+  fn get(&self) -> E {
+    let v = self.storage.i_state_get(self.id);
+    E::from_i64(v).unwrap_or_else(|| panic!("{} is not a valid {}", v, std::any::type_name::<E>()))
+  }
+  fn set(&self, v: E) {
+    self.storage.i_state_set(self.id, v.to_i64())
+  }
+}
+
+/// Whether the integer stored for a discrete state is a value of its type
+pub trait ValidState {
+  fn is_valid(&self) -> bool;
+}
+
+impl<'a> ValidState for DiscreteState<'a, i64> {
+  fn is_valid(&self) -> bool {
+    true
+  }
+}
+
+impl<'a, E: StateEnum> ValidState for DiscreteState<'a, E> {
+  fn is_valid(&self) -> bool {
+    E::from_i64(self.storage.i_state_get(self.id)).is_some()
+  }
+}
+
+impl<'a, E: StateEnum> Initial<E> for DiscreteState<'a, E> {
+  fn init(mut self, v: E) -> Self {
+    self.initial = Some(v);
+    Access::set(&self, v);
+    self
+  }
+}
+
+
+/** Initial values */
+/// Handle that remembers the value passed to `Initial::init`
+pub trait Reset {
+  /// Writes the initial value back, handles without one are left unchanged
+  fn reset(&self);
+}
+
+impl<'a, T: Copy> Reset for Parameter<'a, T> where Self: Access<T> {
+  fn reset(&self) {
+    if let Some(v) = self.initial {
+      self.set(v)
+    }
+  }
+}
+
+impl<'a, T: Copy> Reset for DiscreteState<'a, T> where Self: Access<T> {
+  fn reset(&self) {
+    if let Some(v) = self.initial {
+      self.set(v)
+    }
+  }
+}
+
+impl<'a, T: Copy> Reset for ContinuousState<'a, T> where Self: Access<T> {
+  fn reset(&self) {
+    if let Some(v) = self.initial {
+      self.set(v)
+    }
+  }
+}
+
+pub enum InitialValue<'b> {
+  Parameter(&'b dyn Reset),
+  State(&'b dyn Reset),
+}
+
+
+/** Ports */
+pub struct InputPort {
+  pub name: &'static str,
+  /// The output this input is connected to, if any
+  pub source: Option<SystemStorageItemId>,
+}
+
+impl InputPort {
+  pub fn new(name: &'static str, source: Option<SystemStorageItemId>) -> InputPort {
+    InputPort { name, source }
+  }
+}
+
+pub struct OutputPort {
+  pub name: &'static str,
+  pub id: SystemStorageItemId,
+}
+
+impl OutputPort {
+  pub fn new(name: &'static str, id: SystemStorageItemId) -> OutputPort {
+    OutputPort { name, id }
+  }
+}
+
+/// Parameter, state or output of a block by field name, with one item per array element
+pub struct NamedItem {
+  pub name: &'static str,
+  pub items: Vec<SystemStorageItemId>,
+}
+
+impl NamedItem {
+  pub fn new<T: Into<SystemStorageItemId>>(name: &'static str, item: T) -> NamedItem {
+    NamedItem { name, items: vec![item.into()] }
+  }
+
+  pub fn array(name: &'static str, items: Vec<SystemStorageItemId>) -> NamedItem {
+    NamedItem { name, items }
+  }
+}
+
+/// The inputs an output is computed from directly, rather than through the block state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feedthrough {
+  pub output: &'static str,
+  pub inputs: &'static [&'static str],
+}
+
+/// When the computations of a block run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleTime {
+  /// At every evaluation of the system, including the intermediate stages of the integrator
+  Continuous,
+  /// At `offset + k * period` for k = 0, 1, ..., outputs are held in between
+  Discrete { period: f64, offset: f64 },
+  /// Same as the blocks feeding the inputs, continuous if they differ
+  Inherited,
+  /// Only at the event times located by the simulator
+  Triggered,
+}
+
+impl SampleTime {
+  pub const fn discrete(period: f64) -> SampleTime {
+    SampleTime::Discrete { period, offset: 0.0 }
+  }
+
+  /// Whether a discrete sample time has a positive, finite period and a finite offset
+  pub fn is_valid(&self) -> bool {
+    match *self {
+      SampleTime::Discrete { period, offset } => period.is_finite() && period > 0.0 && offset.is_finite(),
+      _ => true,
+    }
+  }
+
+  /// Whether `t` is a sample hit of a discrete sample time
+  pub fn is_hit(&self, t: f64) -> bool {
+    match *self {
+      SampleTime::Discrete { period, offset } => {
+        let k = ((t - offset) / period).round();
+        k >= 0.0 && (t - (offset + k * period)).abs() <= 1e-9 * period
+      },
+      _ => false,
+    }
+  }
+
+  /// First sample hit of a discrete sample time after `t`
+  pub fn next_hit(&self, t: f64) -> Option<f64> {
+    match *self {
+      SampleTime::Discrete { period, offset } => {
+        let k = ((t - offset) / period).floor().max(-1.0) + 1.0;
+        let hit = offset + k * period;
+        Some(if hit - t <= 1e-9 * period { hit + period } else { hit })
+      },
+      _ => None,
+    }
+  }
+}
+
 pub trait IBlock {
-  fn get_computation(&self) -> BlockComputation;
+  fn get_computation(&self) -> BlockComputation<'_>;
+  fn input_ports(&self) -> Vec<InputPort>;
+  fn output_ports(&self) -> Vec<OutputPort>;
+
+  /// Per-output direct feedthrough. Outputs missing from the list depend on all inputs.
+  /// Without a declaration, `Functional` and `Mixed` blocks feed all inputs through to all outputs
+  /// and `State` blocks none.
+  /// When declared, the state update of a `Mixed` block runs after all outputs, as for `State` blocks.
+  fn feedthrough(&self) -> Option<&'static [Feedthrough]> {
+    None
+  }
+
+  /// Current values of the zero-crossing functions of the block. The solver locates the time
+  /// at which any of them changes sign and applies the discrete state updates there.
+  fn zero_crossings(&self) -> Vec<f64> {
+    vec![]
+  }
+
+  fn sample_time(&self) -> SampleTime {
+    SampleTime::Continuous
+  }
+
+  /// Parameters, states and outputs of the block by field name, see `ISystem::registry`.
+  /// Blocks that do not list them only expose their outputs.
+  fn named_items(&self) -> Vec<NamedItem> {
+    self.output_ports().into_iter().map(|port| NamedItem::new(port.name, port.id)).collect()
+  }
+
+  /// Parameters and states restored by `ISystem::reset`
+  fn initial_values(&self) -> Vec<InitialValue<'_>> {
+    vec![]
+  }
+
+  /// Names of the discrete states holding an integer that is no variant of their enum,
+  /// checked by `ISystem::restore`
+  fn invalid_states(&self) -> Vec<&'static str> {
+    vec![]
+  }
+}
+
+
+/** Generated code */
+/// Implemented by `#[derive(Block)]` from the fields of the block, used by `#[block_impl]`
+/// to implement `ApplyOutputUpdate`, `ApplyStateUpdate` and `IBlock`
+pub trait BlockFields {
+  /// One field per output
+  type OutputUpdate;
+  /// `x_dot` and `x: Option<_>` per continuous state `x`, `x: Option<_>` per discrete state
+  type StateUpdate;
+
+  const FEEDTHROUGH: Option<&'static [Feedthrough]>;
+
+  fn write_outputs(&self, update: Self::OutputUpdate);
+  /// Sets the derivatives, and the states that are `Some`
+  fn write_states(&self, update: Self::StateUpdate);
+  fn port_list(&self) -> (Vec<InputPort>, Vec<OutputPort>);
+  fn field_items(&self) -> Vec<NamedItem>;
+  fn field_initial_values(&self) -> Vec<InitialValue<'_>>;
+  /// Scalar discrete states that are not `ValidState::is_valid`
+  fn field_invalid_states(&self) -> Vec<&'static str>;
+
+  /// Whether every output is declared with `#[feedthrough()]` not to read any input. The computation
+  /// of a block with a state update is then `State`, otherwise `Mixed`.
+  fn state_only_outputs() -> bool {
+    Self::FEEDTHROUGH.is_some_and(|items| items.iter().all(|x| x.inputs.is_empty()))
+  }
 }
@@ -0,0 +1,86 @@
+use std::fmt;
+
+use crate::schedule::{AlgebraicLoop, ValidationError};
+use crate::system::{SystemStorageItemId, SystemSize, DType};
+
+/// Errors reported by the fallible operations of the crate
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// Inputs not connected to any output or invalid sample times
+    Validation(ValidationError),
+    /// Block computations cannot be ordered
+    AlgebraicLoops(Vec<AlgebraicLoop>),
+    /// Item outside of the storage it is read from, e.g. a writer field of another system
+    StorageIndex { id: SystemStorageItemId, size: usize },
+    /// Continuous state that became NaN or infinite during the simulation
+    NonFinite { time: f64, state: usize, value: f64 },
+    /// Simulator settings that cannot be run, e.g. a non-positive output interval
+    Settings(String),
+    /// Integrator that cannot complete a step, e.g. without convergence at the minimum step size
+    Numerical(String),
+    /// Storage too small for a system, or snapshot taken from a storage of different sizes
+    SizeMismatch { expected: Box<SystemSize>, found: Box<SystemSize> },
+    /// Path that is not a parameter of the system
+    UnknownParameter(String),
+    /// Value that does not fit the dtype of its parameter
+    TypeMismatch { path: String, expected: DType },
+    /// Paths of enum discrete states holding an integer that is no variant, e.g. from a snapshot file
+    InvalidStates(Vec<String>),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Malformed or unsupported file contents
+pub(crate) fn invalid_data(message: String) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Validation(e) => write!(f, "invalid system: {}", e),
+            Error::AlgebraicLoops(loops) => {
+                let report: Vec<String> = loops.iter().map(|l| l.to_string()).collect();
+                write!(f, "cannot order block computations: {}", report.join("; "))
+            },
+            Error::StorageIndex { id, size } => write!(f, "{:?} is outside of the storage of size {}", id, size),
+            Error::NonFinite { time, state, value } => write!(f, "state {} is {} at t = {}", state, value, time),
+            Error::Settings(message) => write!(f, "invalid simulator settings: {}", message),
+            Error::Numerical(message) => write!(f, "numerical failure: {}", message),
+            Error::UnknownParameter(path) => write!(f, "`{}` is not a parameter", path),
+            Error::TypeMismatch { path, expected } => write!(f, "`{}` expects a value of type {:?}", path, expected),
+            Error::InvalidStates(paths) => write!(f, "no valid variant in `{}`", paths.join("`, `")),
+            Error::SizeMismatch { expected, found } => write!(f, "expected storage sizes {:?}, found {:?}", expected, found),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Validation(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(e: ValidationError) -> Self {
+        Error::Validation(e)
+    }
+}
@@ -1,4 +1,12 @@
 pub mod block;
 pub mod system;
+pub mod schedule;
+pub mod solver;
+pub mod simulator;
 pub mod writer;
+pub mod snapshot;
+pub mod registry;
+pub mod params;
+pub mod error;
 
+pub use error::{Error, Result};
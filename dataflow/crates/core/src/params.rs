@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value as Json;
+
+use crate::registry::Registry;
+use crate::system::{SystemStorage, SystemStorageItemId, ItemKind, DType, Value};
+use crate::error::{Error, Result, invalid_data};
+
+/// Parameter values by registry path, e.g. `thermal_mass.cp`.
+/// In files, parameters are grouped by block and arrays are written as lists:
+/// ```toml
+/// [thermal_mass]
+/// cp = 4000.0
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParameterSet {
+    values: BTreeMap<String, Value>,
+}
+
+impl ParameterSet {
+    pub fn new() -> ParameterSet {
+        ParameterSet::default()
+    }
+
+    /// Current values of all parameters in the registry
+    pub fn from_storage(registry: &Registry, storage: &dyn SystemStorage) -> ParameterSet {
+        ParameterSet {
+            values: registry.iter()
+                .filter(|(_, id)| id.kind() == ItemKind::Parameter)
+                .map(|(path, id)| (path.to_string(), storage.value(id)))
+                .collect()
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<Value> {
+        self.values.get(path).copied()
+    }
+
+    pub fn insert(&mut self, path: &str, value: Value) {
+        self.values.insert(path.to_string(), value);
+    }
+
+    /// Writes the values to storage. Nothing is written if any path is not a parameter
+    /// or any value does not fit its parameter; integers are accepted for real parameters.
+    pub fn apply(&self, registry: &Registry, storage: &dyn SystemStorage) -> Result<()> {
+        let items = self.values.iter().map(|(path, value)| {
+            let id = registry.get(path)
+                .filter(|id| id.kind() == ItemKind::Parameter)
+                .ok_or_else(|| Error::UnknownParameter(path.clone()))?;
+            let value = match (id.dtype(), *value) {
+                (DType::Real, Value::Int(v)) => Value::Real(v as f64),
+                (DType::Real, Value::Real(_)) | (DType::Bool, Value::Bool(_)) | (DType::Int, Value::Int(_)) => *value,
+                (expected, _) => return Err(Error::TypeMismatch { path: path.clone(), expected }),
+            };
+            Ok((id, value))
+        }).collect::<Result<Vec<_>>>()?;
+
+        for (id, value) in items {
+            match (id, value) {
+                (SystemStorageItemId::RealParameter(i), Value::Real(v)) => storage.r_param_set(i, v),
+                (SystemStorageItemId::BoolParameter(i), Value::Bool(v)) => storage.b_param_set(i, v),
+                (SystemStorageItemId::IntParameter(i), Value::Int(v)) => storage.i_param_set(i, v),
+                _ => unreachable!("values are converted to the dtype of their parameter"),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_json(text: &str) -> Result<ParameterSet> {
+        let json: Json = serde_json::from_str(text).map_err(|e| invalid_data(e.to_string()))?;
+        ParameterSet::from_tree(&json)
+    }
+
+    /// Fails if a real value is NaN or infinite, which JSON cannot represent
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.to_tree()?).expect("parameters are valid JSON"))
+    }
+
+    pub fn from_toml(text: &str) -> Result<ParameterSet> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| invalid_data(e.to_string()))?;
+        ParameterSet::from_tree(&serde_json::to_value(table).map_err(|e| invalid_data(e.to_string()))?)
+    }
+
+    /// Fails if a real value is NaN or infinite
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(&self.to_tree()?).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    /// Loads a `.toml` or `.json` file
+    pub fn load(path: &str) -> Result<ParameterSet> {
+        let text = std::fs::read_to_string(path)?;
+        match extension(path)? {
+            Format::Toml => ParameterSet::from_toml(&text),
+            Format::Json => ParameterSet::from_json(&text),
+        }
+    }
+
+    /// Saves to a `.toml` or `.json` file
+    pub fn save(&self, path: &str) -> Result<()> {
+        let text = match extension(path)? {
+            Format::Toml => self.to_toml()?,
+            Format::Json => self.to_json()?,
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    fn from_tree(tree: &Json) -> Result<ParameterSet> {
+        let blocks = tree.as_object().ok_or_else(|| invalid_data("expected a table of blocks".to_string()))?;
+        let mut set = ParameterSet::new();
+        for (block, fields) in blocks {
+            let fields = fields.as_object()
+                .ok_or_else(|| invalid_data(format!("expected a table of parameters for `{}`", block)))?;
+            for (field, value) in fields {
+                let path = format!("{}.{}", block, field);
+                if value.is_array() {
+                    let mut elements = vec![];
+                    flatten(&path, value, &mut elements)?;
+                    for (i, element) in elements.into_iter().enumerate() {
+                        set.insert(&format!("{}[{}]", path, i), element);
+                    }
+                } else {
+                    set.insert(&path, scalar(&path, value)?);
+                }
+            }
+        }
+        Ok(set)
+    }
+
+    fn to_tree(&self) -> Result<Json> {
+        if let Some((path, value)) = self.values.iter().find(|(_, v)| matches!(v, Value::Real(x) if !x.is_finite())) {
+            return Err(invalid_data(format!("`{}` is {:?}, which cannot be saved", path, value)));
+        }
+        let mut blocks: BTreeMap<&str, BTreeMap<&str, Field>> = BTreeMap::new();
+        for (path, value) in &self.values {
+            let (block, field) = path.split_once('.').unwrap_or(("", path));
+            let fields = blocks.entry(block).or_default();
+            match field.strip_suffix(']').and_then(|x| x.split_once('[')) {
+                Some((name, index)) => {
+                    let index: usize = index.parse().unwrap_or_default();
+                    if let Field::Array(elements) = fields.entry(name).or_insert(Field::Array(BTreeMap::new())) {
+                        elements.insert(index, *value);
+                    }
+                },
+                None => {
+                    fields.insert(field, Field::Scalar(*value));
+                },
+            }
+        }
+        Ok(Json::Object(blocks.into_iter().map(|(block, fields)| {
+            (block.to_string(), Json::Object(fields.into_iter().map(|(name, field)| {
+                let value = match field {
+                    Field::Scalar(value) => json(value),
+                    Field::Array(elements) => Json::Array(elements.into_values().map(json).collect()),
+                };
+                (name.to_string(), value)
+            }).collect()))
+        }).collect()))
+    }
+}
+
+enum Field {
+    Scalar(Value),
+    /// Elements by index
+    Array(BTreeMap<usize, Value>),
+}
+
+enum Format {
+    Toml,
+    Json,
+}
+
+fn extension(path: &str) -> Result<Format> {
+    match std::path::Path::new(path).extension().and_then(|x| x.to_str()) {
+        Some("toml") => Ok(Format::Toml),
+        Some("json") => Ok(Format::Json),
+        _ => Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput, format!("{} is neither a .toml nor a .json file", path)
+        ))),
+    }
+}
+
+/// Elements of nested lists, e.g. of a matrix, row by row
+fn flatten(path: &str, value: &Json, elements: &mut Vec<Value>) -> Result<()> {
+    match value {
+        Json::Array(items) => items.iter().try_for_each(|item| flatten(path, item, elements)),
+        _ => {
+            elements.push(scalar(path, value)?);
+            Ok(())
+        },
+    }
+}
+
+fn scalar(path: &str, value: &Json) -> Result<Value> {
+    match value {
+        Json::Bool(v) => Ok(Value::Bool(*v)),
+        Json::Number(v) => match v.as_i64() {
+            Some(v) => Ok(Value::Int(v)),
+            None => Ok(Value::Real(v.as_f64().unwrap_or(f64::NAN))),
+        },
+        _ => Err(invalid_data(format!("`{}` is not a number or boolean", path))),
+    }
+}
+
+fn json(value: Value) -> Json {
+    match value {
+        Value::Real(v) => Json::from(v),
+        Value::Bool(v) => Json::from(v),
+        Value::Int(v) => Json::from(v),
+    }
+}
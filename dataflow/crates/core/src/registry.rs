@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+
+use crate::block::NamedItem;
+use crate::system::{SystemStorage, SystemStorageItemId};
+
+/// Storage items of a system by dotted path `block.field`, array elements as `block.field[i]`
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    items: BTreeMap<String, SystemStorageItemId>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn insert(&mut self, block: &str, item: NamedItem) {
+        if let [id] = item.items[..] {
+            self.items.insert(format!("{}.{}", block, item.name), id);
+        } else {
+            for (i, id) in item.items.into_iter().enumerate() {
+                self.items.insert(format!("{}.{}[{}]", block, item.name, i), id);
+            }
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<SystemStorageItemId> {
+        self.items.get(path).copied()
+    }
+
+    /// Path of an item, e.g. to label storage dumps
+    pub fn path(&self, id: SystemStorageItemId) -> Option<&str> {
+        self.items.iter().find(|(_, item)| **item == id).map(|(path, _)| path.as_str())
+    }
+
+    /// Items sorted by path
+    pub fn iter(&self) -> impl Iterator<Item = (&str, SystemStorageItemId)> {
+        self.items.iter().map(|(path, id)| (path.as_str(), *id))
+    }
+
+    /// One `path = value` line per item
+    pub fn dump(&self, storage: &dyn SystemStorage) -> String {
+        self.iter().map(|(path, id)| format!("{} = {}\n", path, storage.value(id))).collect()
+    }
+}
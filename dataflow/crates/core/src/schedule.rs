@@ -0,0 +1,476 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::fmt;
+
+use crate::block::{IBlock, BlockComputation, UpdateComputation, OutputPort, SampleTime};
+use crate::system::SystemStorageItemId;
+
+/** Connection graph */
+pub struct DependencyGraph<'a> {
+    pub names: Vec<&'static str>,
+    pub blocks: Vec<&'a dyn IBlock>,
+    /// Output ports of all blocks, together with the index of the owning block
+    pub outputs: Vec<(usize, OutputPort)>,
+    /// For each output, the outputs it is computed from directly and the inputs reading them
+    pub dependencies: Vec<Vec<(usize, &'static str)>>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    pub fn new(named_blocks: Vec<(&'static str, &'a dyn IBlock)>) -> DependencyGraph<'a> {
+        let (names, blocks): (Vec<_>, Vec<_>) = named_blocks.into_iter().unzip();
+        let outputs: Vec<(usize, OutputPort)> = blocks.iter().enumerate()
+            .flat_map(|(i, block)| block.output_ports().into_iter().map(move |port| (i, port)))
+            .collect();
+        let producers: HashMap<SystemStorageItemId, usize> = outputs.iter().enumerate()
+            .map(|(o, (_, port))| (port.id, o))
+            .collect();
+
+        let dependencies = outputs.iter().map(|(i, output)| {
+            let block = blocks[*i];
+            let mut deps: Vec<(usize, &'static str)> = block.input_ports().iter()
+                .filter(|input| feeds_through(block, output.name, input.name))
+                .filter_map(|input| Some((*producers.get(&input.source?)?, input.name)))
+                .collect();
+            deps.sort();
+            deps.dedup();
+            deps
+        }).collect();
+
+        DependencyGraph { names, blocks, outputs, dependencies }
+    }
+
+    /// Topological order of the outputs, preferring lower indices when there is a choice.
+    /// Returns `None` if the graph contains a cycle.
+    pub fn sorted(&self) -> Option<Vec<usize>> {
+        let n = self.outputs.len();
+        let mut remaining: Vec<usize> = self.dependencies.iter().map(|deps| deps.len()).collect();
+        let mut dependents: Vec<Vec<usize>> = vec![vec![]; n];
+        for (o, deps) in self.dependencies.iter().enumerate() {
+            for &(d, _) in deps {
+                dependents[d].push(o);
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = (0..n).filter(|&o| remaining[o] == 0).collect();
+        let mut order = Vec::with_capacity(n);
+        while let Some(o) = ready.pop_first() {
+            order.push(o);
+            for &p in &dependents[o] {
+                remaining[p] -= 1;
+                if remaining[p] == 0 {
+                    ready.insert(p);
+                }
+            }
+        }
+
+        if order.len() == n { Some(order) } else { None }
+    }
+
+    /// Finds one cycle in every strongly connected component of outputs with direct feedthrough.
+    /// Cycles through outputs that depend only on the block state never show up here.
+    pub fn algebraic_loops(&self) -> Vec<AlgebraicLoop> {
+        self.strongly_connected_components().iter()
+            .filter(|component| {
+                component.len() > 1 || self.depends_on(component[0], component[0])
+            })
+            .map(|component| self.describe_loop(&self.find_cycle(component)))
+            .collect()
+    }
+
+    /// Checks that every input is connected to an output of the system and that discrete sample
+    /// times are valid. Returns the outputs nothing reads as warnings.
+    pub fn validate(&self) -> Result<Vec<ConnectionIssue>, ValidationError> {
+        let mut read = vec![false; self.outputs.len()];
+        let mut unconnected_inputs = vec![];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for input in block.input_ports() {
+                let source = input.source
+                    .and_then(|id| self.outputs.iter().position(|(_, port)| port.id == id));
+                match source {
+                    Some(o) => read[o] = true,
+                    None => unconnected_inputs.push(
+                        ConnectionIssue::UnconnectedInput { block: self.names[i], input: input.name }
+                    ),
+                }
+            }
+        }
+
+        let warnings: Vec<ConnectionIssue> = self.outputs.iter().zip(read)
+            .filter(|(_, read)| !read)
+            .map(|((i, port), _)| ConnectionIssue::UnreadOutput { block: self.names[*i], output: port.name })
+            .collect();
+        let invalid_sample_times: Vec<&'static str> = self.blocks.iter().zip(&self.names)
+            .filter(|(block, _)| !block.sample_time().is_valid())
+            .map(|(_, name)| *name)
+            .collect();
+        if unconnected_inputs.is_empty() && invalid_sample_times.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(ValidationError { unconnected_inputs, invalid_sample_times, warnings })
+        }
+    }
+
+    /// Sample time of every block, with inherited sample times resolved from the blocks feeding
+    /// the inputs. Blocks without connected inputs or with differing sources run continuously.
+    pub fn sample_times(&self) -> Vec<SampleTime> {
+        let producers: HashMap<SystemStorageItemId, usize> = self.outputs.iter()
+            .map(|(i, port)| (port.id, *i))
+            .collect();
+        let sources: Vec<Vec<usize>> = self.blocks.iter()
+            .map(|block| block.input_ports().iter()
+                .filter_map(|input| producers.get(&input.source?).copied())
+                .collect())
+            .collect();
+
+        let mut sample_times: Vec<Option<SampleTime>> = self.blocks.iter()
+            .map(|block| match block.sample_time() {
+                SampleTime::Inherited => None,
+                declared => Some(declared),
+            })
+            .collect();
+        // Inherit along chains of blocks until nothing changes
+        loop {
+            let mut changed = false;
+            for i in 0..self.blocks.len() {
+                if sample_times[i].is_some() || sources[i].is_empty() {
+                    continue;
+                }
+                let inherited: Option<Vec<SampleTime>> = sources[i].iter().map(|&s| sample_times[s]).collect();
+                if let Some(inherited) = inherited {
+                    sample_times[i] = Some(if inherited.iter().all(|x| *x == inherited[0]) {
+                        inherited[0]
+                    } else {
+                        SampleTime::Continuous
+                    });
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        sample_times.into_iter().map(|x| x.unwrap_or(SampleTime::Continuous)).collect()
+    }
+
+    fn depends_on(&self, output: usize, other: usize) -> bool {
+        self.dependencies[output].iter().any(|&(d, _)| d == other)
+    }
+
+    /// Tarjan's algorithm over the dependency edges
+    fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        struct Tarjan<'g> {
+            dependencies: &'g [Vec<(usize, &'static str)>],
+            index: Vec<Option<usize>>,
+            low_link: Vec<usize>,
+            on_stack: Vec<bool>,
+            stack: Vec<usize>,
+            next_index: usize,
+            components: Vec<Vec<usize>>,
+        }
+
+        impl<'g> Tarjan<'g> {
+            fn visit(&mut self, v: usize) {
+                self.index[v] = Some(self.next_index);
+                self.low_link[v] = self.next_index;
+                self.next_index += 1;
+                self.stack.push(v);
+                self.on_stack[v] = true;
+
+                for &(w, _) in &self.dependencies[v] {
+                    match self.index[w] {
+                        None => {
+                            self.visit(w);
+                            self.low_link[v] = self.low_link[v].min(self.low_link[w]);
+                        },
+                        Some(w_index) if self.on_stack[w] => {
+                            self.low_link[v] = self.low_link[v].min(w_index);
+                        },
+                        _ => (),
+                    }
+                }
+
+                if Some(self.low_link[v]) == self.index[v] {
+                    let mut component = vec![];
+                    while let Some(w) = self.stack.pop() {
+                        self.on_stack[w] = false;
+                        component.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    component.sort();
+                    self.components.push(component);
+                }
+            }
+        }
+
+        let n = self.outputs.len();
+        let mut tarjan = Tarjan {
+            dependencies: &self.dependencies,
+            index: vec![None; n],
+            low_link: vec![0; n],
+            on_stack: vec![false; n],
+            stack: vec![],
+            next_index: 0,
+            components: vec![],
+        };
+        for v in 0..n {
+            if tarjan.index[v].is_none() {
+                tarjan.visit(v);
+            }
+        }
+        tarjan.components.sort();
+        tarjan.components
+    }
+
+    /// Shortest cycle in data flow direction starting and ending at the first output of the component
+    fn find_cycle(&self, component: &[usize]) -> Vec<usize> {
+        let start = component[0];
+        let mut previous: HashMap<usize, usize> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(v) = queue.pop_front() {
+            for &w in component {
+                if !self.depends_on(w, v) {
+                    continue;
+                }
+                if w == start {
+                    let mut cycle = vec![v];
+                    let mut current = v;
+                    while current != start {
+                        current = previous[&current];
+                        cycle.push(current);
+                    }
+                    cycle.reverse();
+                    return cycle;
+                }
+                if let Entry::Vacant(e) = previous.entry(w) {
+                    e.insert(v);
+                    queue.push_back(w);
+                }
+            }
+        }
+        unreachable!("Strongly connected component without a cycle")
+    }
+
+    fn describe_loop(&self, cycle: &[usize]) -> AlgebraicLoop {
+        let connections = cycle.iter().enumerate().map(|(k, &from)| {
+            let to = cycle[(k + 1) % cycle.len()];
+            let input = self.dependencies[to].iter()
+                .find(|&&(d, _)| d == from)
+                .map(|&(_, input)| input)
+                .expect("Cycle without a connection");
+            let (from_block, output) = &self.outputs[from];
+            LoopConnection {
+                from_block: self.names[*from_block], output: output.name,
+                to_block: self.names[self.outputs[to].0], input,
+            }
+        }).collect();
+        AlgebraicLoop { connections }
+    }
+
+    fn push_output_update(&self, i: usize, sample_time: SampleTime, computations: &mut Vec<ScheduledComputation<'a>>) {
+        let block = self.blocks[i];
+        let mut push = |computation| computations.push(ScheduledComputation { computation, sample_time });
+        match block.get_computation() {
+            BlockComputation::Functional(x) => {
+                push(UpdateComputation::Output(x.output_update_fn));
+            },
+            BlockComputation::State(x) => {
+                push(UpdateComputation::Output(x.output_update_fn));
+            },
+            BlockComputation::Mixed(x) => {
+                if block.feedthrough().is_none() {
+                    push(UpdateComputation::State(x.state_update_fn));
+                }
+                push(UpdateComputation::Output(x.output_update_fn));
+            },
+        }
+    }
+
+    fn push_deferred_state_update(&self, i: usize, sample_time: SampleTime, computations: &mut Vec<ScheduledComputation<'a>>) {
+        let block = self.blocks[i];
+        let mut push = |computation| computations.push(ScheduledComputation { computation, sample_time });
+        match block.get_computation() {
+            BlockComputation::Functional(_) => (),
+            BlockComputation::State(x) => {
+                push(UpdateComputation::State(x.state_update_fn));
+            },
+            BlockComputation::Mixed(x) => {
+                if block.feedthrough().is_some() {
+                    push(UpdateComputation::State(x.state_update_fn));
+                }
+            },
+        }
+    }
+}
+
+/** Schedule */
+/// A block computation together with the resolved sample time of its block
+pub struct ScheduledComputation<'a> {
+    pub computation: UpdateComputation<'a>,
+    pub sample_time: SampleTime,
+}
+
+impl<'a> ScheduledComputation<'a> {
+    pub fn apply(&self) {
+        match &self.computation {
+            UpdateComputation::State(x) => x.f.apply(),
+            UpdateComputation::Output(x) => x.f.apply(),
+        }
+    }
+
+    /// Whether the computation runs at a step of the given kind
+    pub fn runs_at(&self, step: StepKind) -> bool {
+        match (self.sample_time, step) {
+            (SampleTime::Continuous, _) => true,
+            (SampleTime::Discrete { .. }, StepKind::Major(t) | StepKind::Event(t)) => self.sample_time.is_hit(t),
+            (SampleTime::Triggered, StepKind::Event(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Evaluations of the system during a simulation run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepKind {
+    /// Intermediate stage of the integrator, only continuous blocks run
+    Minor,
+    /// Step ending at the given time, discrete blocks run at their sample hits
+    Major(f64),
+    /// Step ending at a located zero crossing, triggered blocks run as well
+    Event(f64),
+}
+
+/// First sample hit of any discrete computation after `t`
+pub fn next_sample_hit(computations: &[ScheduledComputation], t: f64) -> Option<f64> {
+    computations.iter()
+        .filter_map(|c| c.sample_time.next_hit(t))
+        .min_by(f64::total_cmp)
+}
+
+/** Validation */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionIssue {
+    /// Input that is not connected to any output of the system
+    UnconnectedInput { block: &'static str, input: &'static str },
+    /// Output that no input of the system reads
+    UnreadOutput { block: &'static str, output: &'static str },
+}
+
+impl fmt::Display for ConnectionIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionIssue::UnconnectedInput { block, input } => write!(f, "{}.{} is not connected", block, input),
+            ConnectionIssue::UnreadOutput { block, output } => write!(f, "{}.{} is not read", block, output),
+        }
+    }
+}
+
+/// Unconnected inputs and invalid sample times found by `DependencyGraph::validate`, together with the warnings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub unconnected_inputs: Vec<ConnectionIssue>,
+    /// Blocks with a discrete period that is not positive and finite, or an offset that is not finite
+    pub invalid_sample_times: Vec<&'static str>,
+    pub warnings: Vec<ConnectionIssue>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if !self.unconnected_inputs.is_empty() {
+            let issues: Vec<String> = self.unconnected_inputs.iter().map(|x| x.to_string()).collect();
+            parts.push(format!("{} unconnected input(s): {}", issues.len(), issues.join(", ")));
+        }
+        if !self.invalid_sample_times.is_empty() {
+            parts.push(format!("invalid sample time of {}", self.invalid_sample_times.join(", ")));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/** Algebraic loops */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopConnection {
+    pub from_block: &'static str,
+    pub output: &'static str,
+    pub to_block: &'static str,
+    pub input: &'static str,
+}
+
+/// A cycle of connections between blocks with direct feedthrough, in data flow direction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlgebraicLoop {
+    pub connections: Vec<LoopConnection>,
+}
+
+impl AlgebraicLoop {
+    pub fn blocks(&self) -> Vec<&'static str> {
+        self.connections.iter().map(|c| c.from_block).collect()
+    }
+}
+
+impl fmt::Display for AlgebraicLoop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "algebraic loop through [{}]: ", self.blocks().join(", "))?;
+        let connections: Vec<String> = self.connections.iter()
+            .map(|c| format!("{}.{} -> {}.{}", c.from_block, c.output, c.to_block, c.input))
+            .collect();
+        write!(f, "{}", connections.join(", "))
+    }
+}
+
+/// Whether `output` of the block is computed directly from `input`
+fn feeds_through(block: &dyn IBlock, output: &str, input: &str) -> bool {
+    match block.feedthrough() {
+        Some(declared) => declared.iter()
+            .find(|f| f.output == output)
+            .is_none_or(|f| f.inputs.contains(&input)),
+        None => match block.get_computation() {
+            BlockComputation::Functional(_) | BlockComputation::Mixed(_) => true,
+            BlockComputation::State(_) => false,
+        },
+    }
+}
+
+/// Orders the block computations so that every output is computed after the outputs it reads
+/// directly, and the state updates of blocks with state-only outputs run after all outputs.
+/// The output update of a block is repeated when some of its outputs are needed before others
+/// can be computed. Fails with the algebraic loops if the connection graph contains any.
+pub fn schedule(graph: DependencyGraph) -> Result<Vec<ScheduledComputation>, Vec<AlgebraicLoop>> {
+    let order = match graph.sorted() {
+        Some(order) => order,
+        None => return Err(graph.algebraic_loops()),
+    };
+    let sample_times = graph.sample_times();
+
+    let mut computations = vec![];
+    // Number of computations scheduled when an output, resp. the last output update of a block, was done
+    let mut output_done = vec![0; graph.outputs.len()];
+    let mut block_done: Vec<Option<usize>> = vec![None; graph.blocks.len()];
+    for o in order {
+        let i = graph.outputs[o].0;
+        let needed = graph.dependencies[o].iter().map(|&(d, _)| output_done[d]).max().unwrap_or(0);
+        output_done[o] = match block_done[i] {
+            Some(done) if done > needed => done,
+            _ => {
+                graph.push_output_update(i, sample_times[i], &mut computations);
+                block_done[i] = Some(computations.len());
+                computations.len()
+            }
+        };
+    }
+
+    // Blocks without outputs
+    for (i, (done, &sample_time)) in block_done.iter().zip(&sample_times).enumerate() {
+        if done.is_none() {
+            graph.push_output_update(i, sample_time, &mut computations);
+        }
+    }
+    for (i, &sample_time) in sample_times.iter().enumerate() {
+        graph.push_deferred_state_update(i, sample_time, &mut computations);
+    }
+    Ok(computations)
+}
@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+
+use crate::schedule::{next_sample_hit, ConnectionIssue, ScheduledComputation, StepKind};
+use crate::solver::{Integrator, Statistics, get_states, set_states, get_derivatives, set_derivatives};
+use crate::system::ISystem;
+use crate::writer::IWriter;
+use crate::error::{Error, Result};
+
+/// Outcome of a simulation run
+#[derive(Debug)]
+pub struct RunSummary {
+    pub final_time: f64,
+    /// Number of integration steps between evaluations of the system
+    pub steps: usize,
+    /// Number of times the writers were called
+    pub outputs: usize,
+    /// Number of located zero crossings
+    pub events: usize,
+    /// Internal steps of the integrator during the run, without the trial steps that locate events
+    pub statistics: Statistics,
+    pub wall_time: Duration,
+    /// Errors reported by the writers, the run continues past them
+    pub errors: Vec<String>,
+    /// Outputs of the system that nothing reads
+    pub warnings: Vec<ConnectionIssue>,
+}
+
+/// Runs a system from `t_start` to `t_end`, writing the selected signals every `output_interval`.
+/// Steps between evaluations of the system are at most `dt` long and always land on the output times
+/// and on the sample hits of discrete blocks.
+/// When a zero-crossing function changes sign within a step, the step is shortened to end at the
+/// crossing, located by bisection to within `event_tolerance`, by default `1e-9 * output_interval`.
+pub struct Simulator<'w, I: Integrator> {
+    pub integrator: I,
+    pub t_start: f64,
+    pub t_end: f64,
+    pub dt: f64,
+    pub output_interval: f64,
+    pub event_tolerance: Option<f64>,
+    writers: Vec<&'w mut dyn IWriter>,
+}
+
+impl<'w, I: Integrator> Simulator<'w, I> {
+    pub fn new(integrator: I, t_start: f64, t_end: f64, output_interval: f64) -> Simulator<'w, I> {
+        Simulator {
+            integrator, t_start, t_end,
+            dt: output_interval,
+            output_interval,
+            event_tolerance: None,
+            writers: vec![],
+        }
+    }
+
+    pub fn add_writer(&mut self, writer: &'w mut dyn IWriter) {
+        self.writers.push(writer);
+    }
+
+    /// Fails before the first step if the settings are invalid or the system has unconnected inputs,
+    /// invalid sample times or algebraic loops, and stops when a continuous state is no longer finite or the integrator fails.
+    pub fn run<'a, S: ISystem<'a>>(&mut self, system: &'a S) -> Result<RunSummary> {
+        let started = Instant::now();
+        self.validate_settings()?;
+        let warnings = system.validate()?;
+        let computations = system.computations()?;
+        let eps = 1e-9 * self.output_interval;
+
+        let mut summary = RunSummary {
+            final_time: self.t_start,
+            steps: 0,
+            outputs: 0,
+            events: 0,
+            statistics: Statistics::default(),
+            wall_time: Duration::ZERO,
+            errors: vec![],
+            warnings,
+        };
+
+        let statistics = self.integrator.statistics();
+        let mut t = self.t_start;
+        system.step_at(&computations, StepKind::Major(t));
+        self.write_outputs(system, t, &mut summary);
+        let mut crossings = system.zero_crossings();
+
+        while self.t_end - t > eps {
+            let next_output = (self.t_start + summary.outputs as f64 * self.output_interval).min(self.t_end);
+            let next_hit = next_sample_hit(&computations, t).unwrap_or(f64::INFINITY);
+            let mut h = self.dt.min(next_output - t).min(next_hit - t);
+            let x0 = get_states(system.storage());
+            let der0 = get_derivatives(system.storage());
+            let statistics = self.integrator.statistics();
+            system.integrate(&mut self.integrator, &computations, h)?;
+            let states = get_states(system.storage());
+            if let Some((state, &value)) = states.iter().enumerate().find(|(_, x)| !x.is_finite()) {
+                return Err(Error::NonFinite { time: t + h, state, value });
+            }
+
+            let event = !crossings.is_empty() && {
+                system.minor_step(&computations);
+                match self.locate_event(system, &computations, &crossings, (&x0, &der0), statistics, h)? {
+                    Some(h_event) => {
+                        h = h_event;
+                        true
+                    },
+                    None => false,
+                }
+            };
+            t = if next_output - (t + h) <= eps {
+                next_output
+            } else if (next_hit - (t + h)).abs() <= eps {
+                next_hit
+            } else {
+                t + h
+            };
+            summary.steps += 1;
+
+            system.step_at(&computations, if event { StepKind::Event(t) } else { StepKind::Major(t) });
+            if event {
+                // Restart from the discrete states set at the event
+                system.minor_step(&computations);
+                self.integrator.reset();
+                summary.events += 1;
+            }
+            crossings = system.zero_crossings();
+            if next_output - t <= eps {
+                self.write_outputs(system, t, &mut summary);
+            }
+        }
+
+        summary.final_time = t;
+        summary.statistics = self.integrator.statistics().since(statistics);
+        summary.wall_time = started.elapsed();
+        Ok(summary)
+    }
+
+    /// If a zero-crossing function changed sign over the step of length `h` just taken,
+    /// re-integrates from the start of the step up to the first crossing and returns its length.
+    /// The integrator statistics only count the final re-integration on top of `statistics`.
+    fn locate_event<'a, S: ISystem<'a>>(
+        &mut self, system: &'a S, computations: &[ScheduledComputation],
+        crossings: &[f64], start: (&[f64], &[f64]), statistics: Statistics, h: f64
+    ) -> Result<Option<f64>> {
+        let crossed = |values: &[f64]| crossings.iter().zip(values)
+            .any(|(before, after)| before * after < 0.0 || (*after == 0.0 && *before != 0.0));
+        if !crossed(&system.zero_crossings()) {
+            return Ok(None);
+        }
+
+        let mut integrate_to = |h: f64| -> Result<bool> {
+            set_states(system.storage(), start.0);
+            set_derivatives(system.storage(), start.1);
+            self.integrator.reset();
+            self.integrator.set_statistics(statistics);
+            system.integrate(&mut self.integrator, computations, h)?;
+            system.minor_step(computations);
+            Ok(crossed(&system.zero_crossings()))
+        };
+
+        let (mut before, mut after) = (0.0, h);
+        let tolerance = self.event_tolerance.unwrap_or(1e-9 * self.output_interval);
+        while after - before > tolerance {
+            let middle = 0.5 * (before + after);
+            if integrate_to(middle)? {
+                after = middle;
+            } else {
+                before = middle;
+            }
+        }
+        integrate_to(after)?;
+        Ok(Some(after))
+    }
+
+    fn validate_settings(&self) -> Result<()> {
+        let positive = [("dt", self.dt), ("output_interval", self.output_interval)];
+        let finite = [("t_start", self.t_start), ("t_end", self.t_end)];
+        if let Some((name, value)) = positive.iter().find(|(_, x)| !(x.is_finite() && *x > 0.0)) {
+            return Err(Error::Settings(format!("{} must be positive and finite, not {}", name, value)));
+        }
+        if let Some((name, value)) = finite.iter().find(|(_, x)| !x.is_finite()) {
+            return Err(Error::Settings(format!("{} must be finite, not {}", name, value)));
+        }
+        if let Some(tolerance) = self.event_tolerance.filter(|x| !(x.is_finite() && *x > 0.0)) {
+            return Err(Error::Settings(format!("event_tolerance must be positive and finite, not {}", tolerance)));
+        }
+        if self.t_end < self.t_start {
+            return Err(Error::Settings(format!("t_end {} is before t_start {}", self.t_end, self.t_start)));
+        }
+        Ok(())
+    }
+
+    fn write_outputs<'a, S: ISystem<'a>>(&mut self, system: &S, t: f64, summary: &mut RunSummary) {
+        for writer in self.writers.iter_mut() {
+            if let Err(e) = writer.write_step(system.storage(), t) {
+                summary.errors.push(format!("t = {}: {}", t, e));
+            }
+        }
+        summary.outputs += 1;
+    }
+}
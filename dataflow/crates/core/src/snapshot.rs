@@ -0,0 +1,145 @@
+use std::str::FromStr;
+
+use crate::system::{SystemStorage, SystemSize};
+use crate::error::{Error, Result, invalid_data};
+
+/// Copy of all parameters, states, derivatives and outputs of a storage at a
+/// simulation time, e.g. to roll back a what-if scenario or to resume a long run.
+/// The integrator history (step size, BDF history) is not part of the snapshot,
+/// a run resumed from it starts the integrator afresh at `time()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    t: f64,
+    r_param: Vec<f64>,
+    b_param: Vec<bool>,
+    i_param: Vec<i64>,
+    r_state: Vec<f64>,
+    r_state_der: Vec<f64>,
+    r_dstate: Vec<f64>,
+    b_state: Vec<bool>,
+    i_state: Vec<i64>,
+    r_out: Vec<f64>,
+    b_out: Vec<bool>,
+    i_out: Vec<i64>,
+}
+
+impl Snapshot {
+    pub fn of<S: SystemStorage + ?Sized>(storage: &S, t: f64) -> Snapshot {
+        let sizes = storage.sizes();
+        Snapshot {
+            t,
+            r_param: (0..sizes.r_param).map(|i| storage.r_param_get(i)).collect(),
+            b_param: (0..sizes.b_param).map(|i| storage.b_param_get(i)).collect(),
+            i_param: (0..sizes.i_param).map(|i| storage.i_param_get(i)).collect(),
+            r_state: (0..sizes.r_state).map(|i| storage.r_state_get(i)).collect(),
+            r_state_der: (0..sizes.r_state).map(|i| storage.r_state_der_get(i)).collect(),
+            r_dstate: (0..sizes.r_dstate).map(|i| storage.r_dstate_get(i)).collect(),
+            b_state: (0..sizes.b_state).map(|i| storage.b_state_get(i)).collect(),
+            i_state: (0..sizes.i_state).map(|i| storage.i_state_get(i)).collect(),
+            r_out: (0..sizes.r_out).map(|i| storage.r_out_get(i)).collect(),
+            b_out: (0..sizes.b_out).map(|i| storage.b_out_get(i)).collect(),
+            i_out: (0..sizes.i_out).map(|i| storage.i_out_get(i)).collect(),
+        }
+    }
+
+    /// Simulation time the values were taken at
+    pub fn time(&self) -> f64 {
+        self.t
+    }
+
+    pub fn sizes(&self) -> SystemSize {
+        SystemSize {
+            r_param: self.r_param.len(),
+            b_param: self.b_param.len(),
+            i_param: self.i_param.len(),
+            r_state: self.r_state.len(),
+            r_dstate: self.r_dstate.len(),
+            b_state: self.b_state.len(),
+            i_state: self.i_state.len(),
+            r_out: self.r_out.len(),
+            b_out: self.b_out.len(),
+            i_out: self.i_out.len(),
+        }
+    }
+
+    /// Writes the values back, the storage must have the sizes of the snapshot
+    pub fn restore_to<S: SystemStorage + ?Sized>(&self, storage: &S) -> Result<()> {
+        if storage.sizes() != self.sizes() {
+            return Err(Error::SizeMismatch { expected: Box::new(storage.sizes()), found: Box::new(self.sizes()) });
+        }
+        self.r_param.iter().enumerate().for_each(|(i, v)| storage.r_param_set(i, *v));
+        self.b_param.iter().enumerate().for_each(|(i, v)| storage.b_param_set(i, *v));
+        self.i_param.iter().enumerate().for_each(|(i, v)| storage.i_param_set(i, *v));
+        self.r_state.iter().enumerate().for_each(|(i, v)| storage.r_state_set(i, *v));
+        self.r_state_der.iter().enumerate().for_each(|(i, v)| storage.r_state_der_set(i, *v));
+        self.r_dstate.iter().enumerate().for_each(|(i, v)| storage.r_dstate_set(i, *v));
+        self.b_state.iter().enumerate().for_each(|(i, v)| storage.b_state_set(i, *v));
+        self.i_state.iter().enumerate().for_each(|(i, v)| storage.i_state_set(i, *v));
+        self.r_out.iter().enumerate().for_each(|(i, v)| storage.r_out_set(i, *v));
+        self.b_out.iter().enumerate().for_each(|(i, v)| storage.b_out_set(i, *v));
+        self.i_out.iter().enumerate().for_each(|(i, v)| storage.i_out_set(i, *v));
+        Ok(())
+    }
+
+    /// Saves the snapshot as CSV, a `t` line with the time followed by one line
+    /// per lane starting with the lane name
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_path(path)?;
+        fn record<T: ToString>(name: &str, values: &[T]) -> Vec<String> {
+            std::iter::once(name.to_string()).chain(values.iter().map(|v| v.to_string())).collect()
+        }
+        writer.write_record(record("t", &[self.t]))?;
+        writer.write_record(record("r_param", &self.r_param))?;
+        writer.write_record(record("b_param", &self.b_param))?;
+        writer.write_record(record("i_param", &self.i_param))?;
+        writer.write_record(record("r_state", &self.r_state))?;
+        writer.write_record(record("r_state_der", &self.r_state_der))?;
+        writer.write_record(record("r_dstate", &self.r_dstate))?;
+        writer.write_record(record("b_state", &self.b_state))?;
+        writer.write_record(record("i_state", &self.i_state))?;
+        writer.write_record(record("r_out", &self.r_out))?;
+        writer.write_record(record("b_out", &self.b_out))?;
+        writer.write_record(record("i_out", &self.i_out))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a snapshot saved with `save`. The values are not checked against a system,
+    /// `ISystem::restore` rejects enum states without a valid variant.
+    pub fn load(path: &str) -> Result<Snapshot> {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).has_headers(false).from_path(path)?;
+        let mut snapshot = Snapshot::default();
+        let mut t = None;
+        for record in reader.records() {
+            let record = record?;
+            let values = record.iter().skip(1);
+            match record.get(0).unwrap_or_default() {
+                "t" => match parse(values)?[..] {
+                    [value] => t = Some(value),
+                    _ => return Err(invalid_data("`t` must have exactly one value".to_string())),
+                },
+                "r_param" => snapshot.r_param = parse(values)?,
+                "b_param" => snapshot.b_param = parse(values)?,
+                "i_param" => snapshot.i_param = parse(values)?,
+                "r_state" => snapshot.r_state = parse(values)?,
+                "r_state_der" => snapshot.r_state_der = parse(values)?,
+                "r_dstate" => snapshot.r_dstate = parse(values)?,
+                "b_state" => snapshot.b_state = parse(values)?,
+                "i_state" => snapshot.i_state = parse(values)?,
+                "r_out" => snapshot.r_out = parse(values)?,
+                "b_out" => snapshot.b_out = parse(values)?,
+                "i_out" => snapshot.i_out = parse(values)?,
+                lane => return Err(invalid_data(format!("unknown lane `{}`", lane))),
+            }
+        }
+        if snapshot.r_state_der.len() != snapshot.r_state.len() {
+            return Err(invalid_data("r_state and r_state_der have different lengths".to_string()));
+        }
+        snapshot.t = t.ok_or_else(|| invalid_data("missing time `t`".to_string()))?;
+        Ok(snapshot)
+    }
+}
+
+fn parse<'a, T: FromStr, I: Iterator<Item = &'a str>>(values: I) -> Result<Vec<T>> {
+    values.map(|v| v.parse().map_err(|_| invalid_data(format!("invalid value `{}`", v)))).collect()
+}
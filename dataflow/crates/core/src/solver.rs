@@ -0,0 +1,703 @@
+use std::collections::VecDeque;
+
+use crate::system::SystemStorage;
+use crate::error::{Error, Result};
+
+/** Integrators */
+pub trait Integrator {
+    /// Advances the continuous states in `storage` by `dt`.
+    /// On entry the state derivatives in storage correspond to the current states.
+    /// `derivatives` recomputes the state derivatives for the states currently in storage,
+    /// multi-stage methods call it once per additional stage.
+    /// Fails if the method cannot reach `dt`, e.g. when the iterations of an implicit method do not converge.
+    fn advance(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), dt: f64) -> Result<()>;
+
+    /// Discards any information kept from previous steps, e.g. after a discrete event
+    fn reset(&mut self) {}
+
+    /// Counters of the internal steps taken so far, for methods that keep them
+    fn statistics(&self) -> Statistics {
+        Statistics::default()
+    }
+
+    /// Restores the counters, e.g. to discard the trial steps taken while locating an event
+    fn set_statistics(&mut self, _statistics: Statistics) {}
+}
+
+/// Step counters of the adaptive methods
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Statistics {
+    pub accepted_steps: usize,
+    pub rejected_steps: usize,
+    pub jacobian_evaluations: usize,
+}
+
+impl Statistics {
+    /// Counts since `earlier`, e.g. of a single run of a reused integrator
+    pub fn since(&self, earlier: Statistics) -> Statistics {
+        Statistics {
+            accepted_steps: self.accepted_steps - earlier.accepted_steps,
+            rejected_steps: self.rejected_steps - earlier.rejected_steps,
+            jacobian_evaluations: self.jacobian_evaluations - earlier.jacobian_evaluations,
+        }
+    }
+}
+
+pub fn get_states(storage: &dyn SystemStorage) -> Vec<f64> {
+    (0..storage.sizes().r_state).map(|i| storage.r_state_get(i)).collect()
+}
+
+pub fn set_states(storage: &dyn SystemStorage, states: &[f64]) {
+    for (i, value) in states.iter().enumerate() {
+        storage.r_state_set(i, *value);
+    }
+}
+
+pub fn get_derivatives(storage: &dyn SystemStorage) -> Vec<f64> {
+    (0..storage.sizes().r_state).map(|i| storage.r_state_der_get(i)).collect()
+}
+
+pub fn set_derivatives(storage: &dyn SystemStorage, derivatives: &[f64]) {
+    for (i, value) in derivatives.iter().enumerate() {
+        storage.r_state_der_set(i, *value);
+    }
+}
+
+/// Sets the states to `x0 + dt * sum(weight * k)`
+fn set_stage(storage: &dyn SystemStorage, x0: &[f64], dt: f64, stages: &[(f64, &[f64])]) {
+    for (i, x) in x0.iter().enumerate() {
+        let increment: f64 = stages.iter().map(|(weight, k)| weight * k[i]).sum();
+        storage.r_state_set(i, x + dt * increment);
+    }
+}
+
+/// Explicit Euler
+#[derive(Default, Clone, Copy)]
+pub struct Euler;
+
+impl Integrator for Euler {
+    fn advance(&mut self, storage: &dyn SystemStorage, _derivatives: &mut dyn FnMut(), dt: f64) -> Result<()> {
+        let x0 = get_states(storage);
+        let k1 = get_derivatives(storage);
+        set_stage(storage, &x0, dt, &[(1.0, &k1)]);
+        Ok(())
+    }
+}
+
+/// Heun's method (explicit trapezoidal rule), second order
+#[derive(Default, Clone, Copy)]
+pub struct Heun;
+
+impl Integrator for Heun {
+    fn advance(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), dt: f64) -> Result<()> {
+        let x0 = get_states(storage);
+        let k1 = get_derivatives(storage);
+
+        set_stage(storage, &x0, dt, &[(1.0, &k1)]);
+        derivatives();
+        let k2 = get_derivatives(storage);
+
+        set_stage(storage, &x0, dt, &[(0.5, &k1), (0.5, &k2)]);
+        Ok(())
+    }
+}
+
+/// Classic fourth order Runge-Kutta method
+#[derive(Default, Clone, Copy)]
+pub struct RungeKutta4;
+
+impl Integrator for RungeKutta4 {
+    fn advance(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), dt: f64) -> Result<()> {
+        let x0 = get_states(storage);
+        let k1 = get_derivatives(storage);
+
+        set_stage(storage, &x0, dt, &[(0.5, &k1)]);
+        derivatives();
+        let k2 = get_derivatives(storage);
+
+        set_stage(storage, &x0, dt, &[(0.5, &k2)]);
+        derivatives();
+        let k3 = get_derivatives(storage);
+
+        set_stage(storage, &x0, dt, &[(1.0, &k3)]);
+        derivatives();
+        let k4 = get_derivatives(storage);
+
+        set_stage(storage, &x0, dt, &[
+            (1.0 / 6.0, &k1), (1.0 / 3.0, &k2), (1.0 / 3.0, &k3), (1.0 / 6.0, &k4)
+        ]);
+        Ok(())
+    }
+}
+
+/// Adaptive Dormand-Prince 5(4) method.
+/// Each call to `advance` takes as many internal steps as the tolerances require. The last one is
+/// not clipped at `dt`: its continuous extension gives the states at `dt`, and the next call
+/// continues the step as long as the system is unchanged, so frequent outputs do not limit the step size.
+/// Fails when the error estimate is still above the tolerances at `h_min`.
+pub struct DormandPrince {
+    pub rtol: f64,
+    pub atol: f64,
+    pub h_min: f64,
+    pub h_max: f64,
+    /// Step size for the next internal step, estimated on the first call if not set
+    pub h: Option<f64>,
+    pub accepted_steps: usize,
+    pub rejected_steps: usize,
+    /// Step ending after the time reached by the last call
+    pending: Option<DenseStep>,
+}
+
+/// Accepted step of `DormandPrince` with the states left in storage at `s` into the step
+struct DenseStep {
+    x0: Vec<f64>,
+    x1: Vec<f64>,
+    /// Stage derivatives, the last one at `x1`
+    k: Vec<Vec<f64>>,
+    h: f64,
+    s: f64,
+    x: Vec<f64>,
+    /// State derivatives at `x`, to detect changes of the system between calls
+    f: Vec<f64>,
+}
+
+impl DenseStep {
+    /// Fourth order continuous extension of the step
+    fn interpolate(&self, s: f64) -> Vec<f64> {
+        let theta = s / self.h;
+        (0..self.x0.len()).map(|i| {
+            let r2 = self.x1[i] - self.x0[i];
+            let r3 = self.h * self.k[0][i] - r2;
+            let r4 = r2 - self.h * self.k[6][i] - r3;
+            let r5 = self.h * DormandPrince::D.iter().zip(&self.k).map(|(d, k)| d * k[i]).sum::<f64>();
+            self.x0[i] + theta * (r2 + (1.0 - theta) * (r3 + theta * (r4 + (1.0 - theta) * r5)))
+        }).collect()
+    }
+}
+
+impl DormandPrince {
+    const A: [&'static [f64]; 6] = [
+        &[1.0 / 5.0],
+        &[3.0 / 40.0, 9.0 / 40.0],
+        &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+        &[19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0],
+        &[9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0],
+        &[35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+    ];
+    /// Difference between the fifth and fourth order weights
+    const E: [f64; 7] = [
+        71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0, -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0
+    ];
+    /// Weights of the continuous extension, Hairer, Nørsett and Wanner
+    const D: [f64; 7] = [
+        -12715105075.0 / 11282082432.0, 0.0, 87487479700.0 / 32700410799.0, -10690763975.0 / 1880347072.0,
+        701980252875.0 / 199316789632.0, -1453857185.0 / 822651844.0, 69997945.0 / 29380423.0
+    ];
+
+    pub fn new(rtol: f64, atol: f64) -> DormandPrince {
+        DormandPrince {
+            rtol, atol,
+            h_min: 1e-12,
+            h_max: f64::INFINITY,
+            h: None,
+            accepted_steps: 0,
+            rejected_steps: 0,
+            pending: None,
+        }
+    }
+
+    /// Continues the pending step up to `dt` if the states and derivatives in storage are the ones
+    /// it left, returns the time reached
+    fn continue_pending(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), dt: f64) -> f64 {
+        let Some(step) = self.pending.take() else {
+            return 0.0;
+        };
+        if get_states(storage) != step.x {
+            return 0.0;
+        }
+        derivatives();
+        if get_derivatives(storage) != step.f {
+            return 0.0;
+        }
+        let remaining = step.h - step.s;
+        if remaining - dt > 1e-12 * dt {
+            let s = step.s + dt;
+            self.interpolate_to(storage, derivatives, step, s);
+            return dt;
+        }
+        set_states(storage, &step.x1);
+        set_derivatives(storage, &step.k[6]);
+        remaining
+    }
+
+    /// Leaves the states of `step` at `s` in storage and keeps the step for the next call
+    fn interpolate_to(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), mut step: DenseStep, s: f64) {
+        step.s = s;
+        step.x = step.interpolate(s);
+        set_states(storage, &step.x);
+        derivatives();
+        step.f = get_derivatives(storage);
+        self.pending = Some(step);
+    }
+
+    /// Weighted RMS norm relative to the tolerances
+    fn norm(&self, v: &[f64], x0: &[f64], x1: &[f64]) -> f64 {
+        if v.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = v.iter().enumerate().map(|(i, e)| {
+            let scale = self.atol + self.rtol * x0[i].abs().max(x1[i].abs());
+            (e / scale).powi(2)
+        }).sum();
+        (sum / v.len() as f64).sqrt()
+    }
+
+    fn initial_step(&self, x0: &[f64], k1: &[f64], dt: f64) -> f64 {
+        let d0 = self.norm(x0, x0, x0);
+        let d1 = self.norm(k1, x0, x0);
+        let h = if d0 < 1e-5 || d1 < 1e-5 { 1e-6 } else { 0.01 * d0 / d1 };
+        h.min(dt)
+    }
+}
+
+impl Default for DormandPrince {
+    fn default() -> Self {
+        DormandPrince::new(1e-6, 1e-9)
+    }
+}
+
+impl Integrator for DormandPrince {
+    fn advance(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), dt: f64) -> Result<()> {
+        let mut t = self.continue_pending(storage, derivatives, dt);
+        let mut k1 = get_derivatives(storage);
+        let mut h = match self.h {
+            Some(h) => h,
+            None => self.initial_step(&get_states(storage), &k1, dt),
+        }.clamp(self.h_min, self.h_max);
+
+        while dt - t > 1e-12 * dt {
+            let x0 = get_states(storage);
+
+            let mut k = vec![k1.clone()];
+            for a in DormandPrince::A {
+                let stages: Vec<(f64, &[f64])> = a.iter().zip(&k).map(|(w, k)| (*w, k.as_slice())).collect();
+                set_stage(storage, &x0, h, &stages);
+                derivatives();
+                k.push(get_derivatives(storage));
+            }
+            let x1 = get_states(storage);
+
+            let error: Vec<f64> = (0..x0.len())
+                .map(|i| h * DormandPrince::E.iter().zip(&k).map(|(e, k)| e * k[i]).sum::<f64>())
+                .collect();
+            let error_norm = self.norm(&error, &x0, &x1);
+            if !error_norm.is_finite() {
+                set_states(storage, &x0);
+                set_derivatives(storage, &k1);
+                self.h = Some(h);
+                return Err(Error::Numerical(format!("non-finite error estimate at {:e} into a step of {:e}", t, dt)));
+            }
+            if error_norm > 1.0 && h <= self.h_min {
+                set_states(storage, &x0);
+                set_derivatives(storage, &k1);
+                self.h = Some(h);
+                return Err(Error::Numerical(format!(
+                    "error estimate {:e} above the tolerances at the minimum step size {:e}", error_norm, self.h_min
+                )));
+            }
+            let factor = if error_norm == 0.0 { 5.0 } else { (0.9 * error_norm.powf(-0.2)).clamp(0.2, 5.0) };
+
+            if error_norm <= 1.0 {
+                self.accepted_steps += 1;
+                let h_step = h;
+                h = (h_step * factor).clamp(self.h_min, self.h_max);
+                if t + h_step - dt > 1e-12 * dt {
+                    // Interpolated back to `dt`, the rest of the step is taken by the next call
+                    let step = DenseStep { x0, x1, k, h: h_step, s: 0.0, x: vec![], f: vec![] };
+                    self.interpolate_to(storage, derivatives, step, dt - t);
+                    break;
+                }
+                // States and derivatives in storage are the ones at the end of the step
+                t += h_step;
+                k1 = k.pop().unwrap();
+            } else {
+                set_states(storage, &x0);
+                self.rejected_steps += 1;
+                h = (h * factor).max(self.h_min);
+            }
+        }
+        self.h = Some(h);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.pending = None;
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics { accepted_steps: self.accepted_steps, rejected_steps: self.rejected_steps, ..Statistics::default() }
+    }
+
+    fn set_statistics(&mut self, statistics: Statistics) {
+        self.accepted_steps = statistics.accepted_steps;
+        self.rejected_steps = statistics.rejected_steps;
+    }
+}
+
+/// Variable order (BDF1 to BDF5), variable step backward differentiation formulas for stiff systems.
+/// The implicit equations are solved by Newton iterations with a finite difference Jacobian
+/// of the state derivatives, kept across steps until the iterations fail or converge slowly with it.
+/// Each call to `advance` ends exactly at `dt`. The solution history and the Jacobian are kept
+/// between calls and discarded when the states are changed outside of the integrator.
+pub struct Bdf {
+    pub rtol: f64,
+    pub atol: f64,
+    pub h_min: f64,
+    pub h_max: f64,
+    pub max_order: usize,
+    /// Step size for the next internal step, estimated on the first call if not set
+    pub h: Option<f64>,
+    pub order: usize,
+    pub accepted_steps: usize,
+    pub rejected_steps: usize,
+    pub jacobian_evaluations: usize,
+    /// Solution history (time, states), most recent first
+    history: VecDeque<(f64, Vec<f64>)>,
+    /// State derivatives at the most recent solution
+    last_derivatives: Vec<f64>,
+    steps_at_order: usize,
+    time: f64,
+    /// Jacobian kept across steps until Newton iterations fail or converge slowly with it
+    jacobian: Option<Vec<Vec<f64>>>,
+    /// Newton iteration matrix `alpha * I - J` and the `alpha` it was built for
+    iteration_matrix: Option<(f64, Vec<Vec<f64>>)>,
+}
+
+impl Bdf {
+    const MAX_NEWTON_ITERATIONS: usize = 4;
+
+    pub fn new(rtol: f64, atol: f64) -> Bdf {
+        Bdf {
+            rtol, atol,
+            h_min: 1e-12,
+            h_max: f64::INFINITY,
+            max_order: 5,
+            h: None,
+            order: 1,
+            accepted_steps: 0,
+            rejected_steps: 0,
+            jacobian_evaluations: 0,
+            history: VecDeque::new(),
+            last_derivatives: vec![],
+            steps_at_order: 0,
+            time: 0.0,
+            jacobian: None,
+            iteration_matrix: None,
+        }
+    }
+
+    fn norm(&self, v: &[f64], x: &[f64]) -> f64 {
+        if v.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = v.iter().zip(x).map(|(e, x)| (e / (self.atol + self.rtol * x.abs())).powi(2)).sum();
+        (sum / v.len() as f64).sqrt()
+    }
+
+    /// Value at `t` of the polynomial interpolating the first `n` history points
+    fn extrapolate(&self, n: usize, t: f64) -> Vec<f64> {
+        let points: Vec<&(f64, Vec<f64>)> = self.history.iter().take(n).collect();
+        let mut result = vec![0.0; points[0].1.len()];
+        for (j, (t_j, x_j)) in points.iter().enumerate() {
+            let l_j: f64 = points.iter().enumerate()
+                .filter(|(m, _)| *m != j)
+                .map(|(_, (t_m, _))| (t - t_m) / (t_j - t_m))
+                .product();
+            for (r, x) in result.iter_mut().zip(x_j) {
+                *r += l_j * x;
+            }
+        }
+        result
+    }
+
+    /// Local error estimate of the order `q` formula from the difference to the predictor
+    /// through the last `q + 1` solutions. `None` if the history is too short.
+    fn error_estimate(&self, q: usize, t_new: f64, x_new: &[f64]) -> Option<f64> {
+        if q == 0 || self.history.len() < q + 1 {
+            return None;
+        }
+        let predicted = self.extrapolate(q + 1, t_new);
+        let h = t_new - self.history[0].0;
+        let scale = h / (t_new - self.history[q].0);
+        let difference: Vec<f64> = x_new.iter().zip(&predicted).map(|(x, p)| x - p).collect();
+        Some(scale * self.norm(&difference, x_new))
+    }
+
+    fn jacobian(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), x: &[f64], f: &[f64]) -> Vec<Vec<f64>> {
+        self.jacobian_evaluations += 1;
+        let n = x.len();
+        let mut jacobian = vec![vec![0.0; n]; n];
+        for j in 0..n {
+            let delta = f64::EPSILON.sqrt() * x[j].abs().max(1.0);
+            storage.r_state_set(j, x[j] + delta);
+            derivatives();
+            for (i, row) in jacobian.iter_mut().enumerate() {
+                row[j] = (storage.r_state_der_get(i) - f[i]) / delta;
+            }
+            storage.r_state_set(j, x[j]);
+        }
+        jacobian
+    }
+
+    /// Solves the BDF equations for the states at `t_new` with the current order.
+    /// Leaves the solution and its derivatives in storage, `None` if Newton iterations do not converge.
+    /// The Jacobian of a previous step is reused and only evaluated again when the iterations fail with it.
+    fn solve_step(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), t_new: f64) -> Option<Vec<f64>> {
+        let k = self.order;
+        let nodes: Vec<f64> = std::iter::once(t_new).chain(self.history.iter().take(k).map(|p| p.0)).collect();
+        let alpha = lagrange_derivative_at_first(&nodes);
+        // Contribution of the past solutions to the derivative of the interpolating polynomial
+        let n = self.history[0].1.len();
+        let past: Vec<f64> = (0..n)
+            .map(|i| self.history.iter().take(k).zip(&alpha[1..]).map(|(p, a)| a * p.1[i]).sum())
+            .collect();
+
+        let x = if self.history.len() > k {
+            self.extrapolate(k + 1, t_new)
+        } else {
+            let h = t_new - self.history[0].0;
+            self.history[0].1.iter().zip(&self.last_derivatives).map(|(x, f)| x + h * f).collect()
+        };
+        set_states(storage, &x);
+        derivatives();
+        let f = get_derivatives(storage);
+
+        loop {
+            let fresh = self.jacobian.is_none();
+            if fresh {
+                self.jacobian = Some(self.jacobian(storage, derivatives, &x, &f));
+                self.iteration_matrix = None;
+            }
+            // Rebuilt when the step size or the order changed `alpha` significantly
+            if !self.iteration_matrix.as_ref().is_some_and(|(a, _)| (a / alpha[0] - 1.0).abs() <= 0.3) {
+                let jacobian = self.jacobian.as_ref().unwrap();
+                let matrix = jacobian.iter().enumerate().map(|(i, row)| {
+                    row.iter().enumerate().map(|(j, df)| if i == j { alpha[0] - df } else { -df }).collect()
+                }).collect();
+                self.iteration_matrix = Some((alpha[0], matrix));
+            }
+            if let Some(x) = self.newton(storage, derivatives, x.clone(), f.clone(), &past, alpha[0]) {
+                return Some(x);
+            }
+            if fresh {
+                return None;
+            }
+            self.jacobian = None;
+        }
+    }
+
+    /// Newton iterations for `alpha * x + past = f(x)` starting from `x` with derivatives `f`,
+    /// `None` if they diverge, converge slowly or do not converge within `MAX_NEWTON_ITERATIONS`
+    fn newton(
+        &self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(),
+        mut x: Vec<f64>, mut f: Vec<f64>, past: &[f64], alpha: f64
+    ) -> Option<Vec<f64>> {
+        let matrix = &self.iteration_matrix.as_ref()?.1;
+        let mut previous = f64::INFINITY;
+        for _ in 0..Bdf::MAX_NEWTON_ITERATIONS {
+            let residual: Vec<f64> = (0..x.len()).map(|i| -(alpha * x[i] + past[i] - f[i])).collect();
+            let dx = solve_linear(matrix.clone(), residual)?;
+            for (x, dx) in x.iter_mut().zip(&dx) {
+                *x += dx;
+            }
+            set_states(storage, &x);
+            derivatives();
+            f = get_derivatives(storage);
+            let norm = self.norm(&dx, &x);
+            if norm <= 0.1 {
+                return Some(x);
+            }
+            if norm > 0.9 * previous {
+                return None;
+            }
+            previous = norm;
+        }
+        None
+    }
+}
+
+impl Default for Bdf {
+    fn default() -> Self {
+        Bdf::new(1e-6, 1e-9)
+    }
+}
+
+impl Integrator for Bdf {
+    fn advance(&mut self, storage: &dyn SystemStorage, derivatives: &mut dyn FnMut(), dt: f64) -> Result<()> {
+        let x_start = get_states(storage);
+        if self.history.front().map(|p| &p.1) != Some(&x_start) {
+            self.history.clear();
+            self.jacobian = None;
+            self.history.push_front((self.time, x_start));
+            self.order = 1;
+            self.steps_at_order = 0;
+        }
+        self.last_derivatives = get_derivatives(storage);
+
+        let t_end = self.time + dt;
+        let mut h = match self.h {
+            Some(h) => h,
+            None => {
+                let x0 = &self.history[0].1;
+                let d1 = self.norm(&self.last_derivatives, x0);
+                if d1 < 1e-5 { dt } else { 0.01 / d1 }
+            }
+        }.clamp(self.h_min, self.h_max);
+
+        while t_end - self.time > 1e-12 * dt {
+            let clipped = h >= t_end - self.time;
+            let h_step = if clipped { t_end - self.time } else { h };
+            let t_new = self.time + h_step;
+
+            let x_new = match self.solve_step(storage, derivatives, t_new) {
+                Some(x) => x,
+                None if h_step > self.h_min => {
+                    set_states(storage, &self.history[0].1);
+                    self.rejected_steps += 1;
+                    h = (0.25 * h_step).max(self.h_min);
+                    continue;
+                },
+                None => return Err(Error::Numerical(format!(
+                    "BDF Newton iterations do not converge at the minimum step size {:e}", self.h_min
+                ))),
+            };
+
+            let k = self.order;
+            let error = match self.error_estimate(k, t_new, &x_new) {
+                Some(error) => error,
+                // Startup: compare with the explicit Euler predictor
+                None => {
+                    let x0 = &self.history[0].1;
+                    let difference: Vec<f64> = (0..x0.len())
+                        .map(|i| x_new[i] - x0[i] - h_step * self.last_derivatives[i])
+                        .collect();
+                    0.5 * self.norm(&difference, &x_new)
+                }
+            };
+
+            if error > 1.0 && h_step > self.h_min {
+                set_states(storage, &self.history[0].1);
+                self.rejected_steps += 1;
+                let factor = (0.9 * error.powf(-1.0 / (k as f64 + 1.0))).clamp(0.2, 0.9);
+                h = (h_step * factor).max(self.h_min);
+                continue;
+            }
+
+            // Order selection among k - 1, k, k + 1, before the new solution enters the history
+            let mut candidates = vec![(k, error)];
+            if self.steps_at_order > k {
+                if let Some(e) = self.error_estimate(k - 1, t_new, &x_new) {
+                    candidates.push((k - 1, e));
+                }
+                if k < self.max_order {
+                    if let Some(e) = self.error_estimate(k + 1, t_new, &x_new) {
+                        candidates.push((k + 1, e));
+                    }
+                }
+            }
+            let step_factor = |q: usize, e: f64| if e == 0.0 { 2.0 } else { 0.9 * e.powf(-1.0 / (q as f64 + 1.0)) };
+            let (mut order, mut factor) = (k, step_factor(k, error));
+            for &(q, e) in &candidates[1..] {
+                // Change order only if it allows a clearly larger step
+                if step_factor(q, e) > 1.2 * factor {
+                    (order, factor) = (q, step_factor(q, e));
+                }
+            }
+
+            self.accepted_steps += 1;
+            self.time = t_new;
+            self.history.push_front((t_new, x_new));
+            self.history.truncate(self.max_order + 2);
+            self.last_derivatives = get_derivatives(storage);
+            if order == k {
+                self.steps_at_order += 1;
+            } else {
+                self.order = order;
+                self.steps_at_order = 0;
+            }
+
+            let proposed = (h_step * factor.clamp(0.2, 2.0)).clamp(self.h_min, self.h_max);
+            h = if clipped { h.max(proposed) } else { proposed };
+        }
+        self.time = t_end;
+        if let Some(front) = self.history.front_mut() {
+            front.0 = t_end;
+        }
+        self.h = Some(h);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+        self.jacobian = None;
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            accepted_steps: self.accepted_steps,
+            rejected_steps: self.rejected_steps,
+            jacobian_evaluations: self.jacobian_evaluations,
+        }
+    }
+
+    fn set_statistics(&mut self, statistics: Statistics) {
+        self.accepted_steps = statistics.accepted_steps;
+        self.rejected_steps = statistics.rejected_steps;
+        self.jacobian_evaluations = statistics.jacobian_evaluations;
+    }
+}
+
+/// Derivatives at `nodes[0]` of the Lagrange basis polynomials for `nodes`
+fn lagrange_derivative_at_first(nodes: &[f64]) -> Vec<f64> {
+    let t = nodes[0];
+    (0..nodes.len()).map(|j| {
+        if j == 0 {
+            nodes[1..].iter().map(|t_m| 1.0 / (t - t_m)).sum()
+        } else {
+            let product: f64 = nodes.iter().enumerate()
+                .filter(|(m, _)| *m != j && *m != 0)
+                .map(|(_, t_m)| (t - t_m) / (nodes[j] - t_m))
+                .product();
+            product / (nodes[j] - t)
+        }
+    }).collect()
+}
+
+/// Gaussian elimination with partial pivoting, `None` if the matrix is singular
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col] == 0.0 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (pivot_rows, rows) = a.split_at_mut(col + 1);
+        let (pivot_b, rows_b) = b.split_at_mut(col + 1);
+        let pivot_row = &pivot_rows[col];
+        for (row, b_row) in rows.iter_mut().zip(rows_b) {
+            let factor = row[col] / pivot_row[col];
+            for (x, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= factor * p;
+            }
+            *b_row -= factor * pivot_b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = ((row + 1)..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
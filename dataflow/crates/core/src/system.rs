@@ -1,20 +1,30 @@
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 
 use const_default::ConstDefault;
 use const_default_derive::ConstDefault;
 
-use crate::block::{BlockSize, IBlock, UpdateComputation, Parameter, ContinuousState, DiscreteState, Output, ReadAccess};
+use crate::block::{BlockSize, IBlock, InitialValue, Parameter, ContinuousState, DiscreteState, Output, RealArray, StateEnum};
+use crate::schedule::{schedule, DependencyGraph, AlgebraicLoop, ConnectionIssue, ValidationError, ScheduledComputation, StepKind};
+use crate::solver::{Integrator, Euler};
+use crate::error::{self, Error};
+use crate::snapshot::Snapshot;
+use crate::registry::Registry;
 
 
 #[allow(dead_code)]
-#[derive(Default, Debug, ConstDefault, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, ConstDefault, PartialEq, Eq)]
 pub struct SystemSize {
     pub r_param: usize,
     pub b_param: usize,
+    pub i_param: usize,
     pub r_state: usize,
+    pub r_dstate: usize,
     pub b_state: usize,
+    pub i_state: usize,
     pub r_out: usize,
     pub b_out: usize,
+    pub i_out: usize,
 }
 
 #[allow(dead_code)]
@@ -28,50 +38,136 @@ impl SystemSize {
         SystemSize { 
             r_param: self.r_param + bs.r_param,
             b_param: self.b_param + bs.b_param,
+            i_param: self.i_param + bs.i_param,
             r_state: self.r_state + bs.r_state,
+            r_dstate: self.r_dstate + bs.r_dstate,
             b_state: self.b_state + bs.b_state,
+            i_state: self.i_state + bs.i_state,
             r_out: self.r_out + bs.r_out,
             b_out: self.b_out + bs.b_out,
+            i_out: self.i_out + bs.i_out,
         }
     }
+
+    /// Whether every lane is at least as large as in `size`
+    pub const fn contains(&self, size: &SystemSize) -> bool {
+        self.r_param >= size.r_param && self.b_param >= size.b_param && self.i_param >= size.i_param
+            && self.r_state >= size.r_state && self.r_dstate >= size.r_dstate
+            && self.b_state >= size.b_state && self.i_state >= size.i_state
+            && self.r_out >= size.r_out && self.b_out >= size.b_out && self.i_out >= size.i_out
+    }
 }
 
+/// Values of all parameters, states and outputs of a system.
+/// Every value is read and written atomically, so storage can be shared between threads.
+/// Concurrent steps of the same system still see each other's intermediate values.
 pub trait SystemStorage : Sync {
     fn sizes(&self) -> SystemSize;
 
-    fn r_param_get(&self, ind: usize) -> &f64;
+    /// Fails if any lane is smaller than in `size`, checked once when a system is built on the storage
+    /// so that its handles stay in range
+    fn check_size(&self, size: SystemSize) -> error::Result<()> {
+        if self.sizes().contains(&size) {
+            Ok(())
+        } else {
+            Err(Error::SizeMismatch { expected: Box::new(size), found: Box::new(self.sizes()) })
+        }
+    }
+
+    fn r_param_get(&self, ind: usize) -> f64;
     fn r_param_set(&self, ind: usize, value: f64);
     
-    fn b_param_get(&self, ind: usize) -> &bool;
+    fn b_param_get(&self, ind: usize) -> bool;
     fn b_param_set(&self, ind: usize, value: bool);
+
+    fn i_param_get(&self, ind: usize) -> i64;
+    fn i_param_set(&self, ind: usize, value: i64);
     
     
-    fn r_state_get(&self, ind: usize) -> &f64;
+    fn r_state_get(&self, ind: usize) -> f64;
     fn r_state_set(&self, ind: usize, value: f64);
-    fn r_state_der_get(&self, ind: usize) -> &f64;
+    fn r_state_der_get(&self, ind: usize) -> f64;
     fn r_state_der_set(&self, ind: usize, value: f64);
+
+    fn r_dstate_get(&self, ind: usize) -> f64;
+    fn r_dstate_set(&self, ind: usize, value: f64);
     
-    fn b_state_get(&self, ind: usize) -> &bool;
+    fn b_state_get(&self, ind: usize) -> bool;
     fn b_state_set(&self, ind: usize, value: bool);
 
-    fn r_out_get(&self, ind: usize) -> &f64;
+    fn i_state_get(&self, ind: usize) -> i64;
+    fn i_state_set(&self, ind: usize, value: i64);
+
+    fn r_out_get(&self, ind: usize) -> f64;
     fn r_out_set(&self, ind: usize, value: f64);
     
-    fn b_out_get(&self, ind: usize) -> &bool;
+    fn b_out_get(&self, ind: usize) -> bool;
     fn b_out_set(&self, ind: usize, value: bool);
 
+    fn i_out_get(&self, ind: usize) -> i64;
+    fn i_out_set(&self, ind: usize, value: i64);
+
+    /// Fails if the item is outside of the storage
+    fn check(&self, id: SystemStorageItemId) -> error::Result<()> {
+        let sizes = self.sizes();
+        let (index, size) = match id {
+            SystemStorageItemId::RealParameter(i) => (i, sizes.r_param),
+            SystemStorageItemId::BoolParameter(i) => (i, sizes.b_param),
+            SystemStorageItemId::IntParameter(i) => (i, sizes.i_param),
+            SystemStorageItemId::RealState(i) => (i, sizes.r_state),
+            SystemStorageItemId::RealDiscreteState(i) => (i, sizes.r_dstate),
+            SystemStorageItemId::BoolState(i) => (i, sizes.b_state),
+            SystemStorageItemId::IntState(i) => (i, sizes.i_state),
+            SystemStorageItemId::RealOutput(i) => (i, sizes.r_out),
+            SystemStorageItemId::BoolOutput(i) => (i, sizes.b_out),
+            SystemStorageItemId::IntOutput(i) => (i, sizes.i_out),
+        };
+        if index < size { Ok(()) } else { Err(Error::StorageIndex { id, size }) }
+    }
+
+    fn value(&self, id: SystemStorageItemId) -> Value {
+        match id {
+            SystemStorageItemId::RealParameter(i) => Value::Real(self.r_param_get(i)),
+            SystemStorageItemId::BoolParameter(i) => Value::Bool(self.b_param_get(i)),
+            SystemStorageItemId::IntParameter(i) => Value::Int(self.i_param_get(i)),
+            SystemStorageItemId::RealState(i) => Value::Real(self.r_state_get(i)),
+            SystemStorageItemId::RealDiscreteState(i) => Value::Real(self.r_dstate_get(i)),
+            SystemStorageItemId::BoolState(i) => Value::Bool(self.b_state_get(i)),
+            SystemStorageItemId::IntState(i) => Value::Int(self.i_state_get(i)),
+            SystemStorageItemId::RealOutput(i) => Value::Real(self.r_out_get(i)),
+            SystemStorageItemId::BoolOutput(i) => Value::Bool(self.b_out_get(i)),
+            SystemStorageItemId::IntOutput(i) => Value::Int(self.i_out_get(i)),
+        }
+    }
+
+    /// Copies all values of the storage, taken at simulation time `t`
+    fn snapshot(&self, t: f64) -> Snapshot {
+        Snapshot::of(self, t)
+    }
+
+    /// Overwrites all values with the ones of the snapshot
+    fn restore(&self, snapshot: &Snapshot) -> error::Result<()> {
+        snapshot.restore_to(self)
+    }
+
     fn print_params(&self) {
         print!("r_param: ");
         for i in 0..self.sizes().r_param {
             print!("{},", self.r_param_get(i));
         }
-        println!("");
+        println!();
 
         print!("b_param: ");
         for i in 0..self.sizes().b_param {
             print!("{},", self.b_param_get(i));
         }
-        println!("");
+        println!();
+
+        print!("i_param: ");
+        for i in 0..self.sizes().i_param {
+            print!("{},", self.i_param_get(i));
+        }
+        println!();
     }
 
     fn print_states_outputs(&self) {
@@ -79,36 +175,262 @@ pub trait SystemStorage : Sync {
         for i in 0..self.sizes().r_state {
             print!("{} ({}),", self.r_state_get(i), self.r_state_der_get(i));
         }
-        println!("");
+        println!();
+
+        print!("r_dstate: ");
+        for i in 0..self.sizes().r_dstate {
+            print!("{},", self.r_dstate_get(i));
+        }
+        println!();
 
         print!("b_state: ");
         for i in 0..self.sizes().b_state {
             print!("{},", self.b_state_get(i));
         }
-        println!("");
+        println!();
+
+        print!("i_state: ");
+        for i in 0..self.sizes().i_state {
+            print!("{},", self.i_state_get(i));
+        }
+        println!();
 
         print!("r_out: ");
         for i in 0..self.sizes().r_out {
             print!("{},", self.r_out_get(i));
         }
-        println!("");
+        println!();
 
         print!("b_out: ");
         for i in 0..self.sizes().b_out {
             print!("{},", self.b_out_get(i));
         }
-        println!("");
+        println!();
+
+        print!("i_out: ");
+        for i in 0..self.sizes().i_out {
+            print!("{},", self.i_out_get(i));
+        }
+        println!();
+    }
+
+}
+
+/// `f64` stored as its bit pattern
+#[derive(Debug, Default)]
+pub struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    pub const fn new(value: f64) -> AtomicF64 {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+/// Storage with buffers allocated at runtime, one per system instance
+pub struct DynamicStorage {
+    sizes: SystemSize,
+    r_param: Box<[AtomicF64]>,
+    b_param: Box<[AtomicBool]>,
+    i_param: Box<[AtomicI64]>,
+    r_state: Box<[AtomicF64]>,
+    r_state_der: Box<[AtomicF64]>,
+    r_dstate: Box<[AtomicF64]>,
+    b_state: Box<[AtomicBool]>,
+    i_state: Box<[AtomicI64]>,
+    r_out: Box<[AtomicF64]>,
+    b_out: Box<[AtomicBool]>,
+    i_out: Box<[AtomicI64]>,
+}
+
+impl DynamicStorage {
+    pub fn new(sizes: SystemSize) -> DynamicStorage {
+        let reals = |n: usize| (0..n).map(|_| AtomicF64::new(0.0)).collect();
+        let bools = |n: usize| (0..n).map(|_| AtomicBool::new(false)).collect();
+        let ints = |n: usize| (0..n).map(|_| AtomicI64::new(0)).collect();
+        DynamicStorage {
+            sizes,
+            r_param: reals(sizes.r_param),
+            b_param: bools(sizes.b_param),
+            i_param: ints(sizes.i_param),
+            r_state: reals(sizes.r_state),
+            r_state_der: reals(sizes.r_state),
+            r_dstate: reals(sizes.r_dstate),
+            b_state: bools(sizes.b_state),
+            i_state: ints(sizes.i_state),
+            r_out: reals(sizes.r_out),
+            b_out: bools(sizes.b_out),
+            i_out: ints(sizes.i_out),
+        }
+    }
+}
+
+impl SystemStorage for DynamicStorage {
+    fn sizes(&self) -> SystemSize {
+        self.sizes
+    }
+
+    fn r_param_get(&self, ind: usize) -> f64 {
+        self.r_param[ind].get()
+    }
+    fn r_param_set(&self, ind: usize, value: f64) {
+        self.r_param[ind].set(value)
+    }
+
+    fn b_param_get(&self, ind: usize) -> bool {
+        self.b_param[ind].load(Ordering::Relaxed)
+    }
+    fn b_param_set(&self, ind: usize, value: bool) {
+        self.b_param[ind].store(value, Ordering::Relaxed)
+    }
+
+    fn i_param_get(&self, ind: usize) -> i64 {
+        self.i_param[ind].load(Ordering::Relaxed)
+    }
+    fn i_param_set(&self, ind: usize, value: i64) {
+        self.i_param[ind].store(value, Ordering::Relaxed)
+    }
+
+    fn r_state_get(&self, ind: usize) -> f64 {
+        self.r_state[ind].get()
+    }
+    fn r_state_set(&self, ind: usize, value: f64) {
+        self.r_state[ind].set(value)
+    }
+    fn r_state_der_get(&self, ind: usize) -> f64 {
+        self.r_state_der[ind].get()
+    }
+    fn r_state_der_set(&self, ind: usize, value: f64) {
+        self.r_state_der[ind].set(value)
+    }
+
+    fn r_dstate_get(&self, ind: usize) -> f64 {
+        self.r_dstate[ind].get()
+    }
+    fn r_dstate_set(&self, ind: usize, value: f64) {
+        self.r_dstate[ind].set(value)
+    }
+
+    fn b_state_get(&self, ind: usize) -> bool {
+        self.b_state[ind].load(Ordering::Relaxed)
+    }
+    fn b_state_set(&self, ind: usize, value: bool) {
+        self.b_state[ind].store(value, Ordering::Relaxed)
+    }
+
+    fn i_state_get(&self, ind: usize) -> i64 {
+        self.i_state[ind].load(Ordering::Relaxed)
+    }
+    fn i_state_set(&self, ind: usize, value: i64) {
+        self.i_state[ind].store(value, Ordering::Relaxed)
     }
 
+    fn r_out_get(&self, ind: usize) -> f64 {
+        self.r_out[ind].get()
+    }
+    fn r_out_set(&self, ind: usize, value: f64) {
+        self.r_out[ind].set(value)
+    }
+
+    fn b_out_get(&self, ind: usize) -> bool {
+        self.b_out[ind].load(Ordering::Relaxed)
+    }
+    fn b_out_set(&self, ind: usize, value: bool) {
+        self.b_out[ind].store(value, Ordering::Relaxed)
+    }
+
+    fn i_out_get(&self, ind: usize) -> i64 {
+        self.i_out[ind].load(Ordering::Relaxed)
+    }
+    fn i_out_set(&self, ind: usize, value: i64) {
+        self.i_out[ind].store(value, Ordering::Relaxed)
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SystemStorageItemId {
     RealParameter(usize),
     BoolParameter(usize),
+    IntParameter(usize),
     RealState(usize),
+    RealDiscreteState(usize),
     BoolState(usize),
+    IntState(usize),
     RealOutput(usize),
     BoolOutput(usize),
+    IntOutput(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Parameter,
+    ContinuousState,
+    DiscreteState,
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DType {
+    Real,
+    Bool,
+    Int,
+}
+
+impl SystemStorageItemId {
+    pub fn kind(&self) -> ItemKind {
+        match self {
+            SystemStorageItemId::RealParameter(_)
+            | SystemStorageItemId::BoolParameter(_)
+            | SystemStorageItemId::IntParameter(_) => ItemKind::Parameter,
+            SystemStorageItemId::RealState(_) => ItemKind::ContinuousState,
+            SystemStorageItemId::RealDiscreteState(_)
+            | SystemStorageItemId::BoolState(_)
+            | SystemStorageItemId::IntState(_) => ItemKind::DiscreteState,
+            SystemStorageItemId::RealOutput(_)
+            | SystemStorageItemId::BoolOutput(_)
+            | SystemStorageItemId::IntOutput(_) => ItemKind::Output,
+        }
+    }
+
+    pub fn dtype(&self) -> DType {
+        match self {
+            SystemStorageItemId::RealParameter(_)
+            | SystemStorageItemId::RealState(_)
+            | SystemStorageItemId::RealDiscreteState(_)
+            | SystemStorageItemId::RealOutput(_) => DType::Real,
+            SystemStorageItemId::BoolParameter(_)
+            | SystemStorageItemId::BoolState(_)
+            | SystemStorageItemId::BoolOutput(_) => DType::Bool,
+            SystemStorageItemId::IntParameter(_)
+            | SystemStorageItemId::IntState(_)
+            | SystemStorageItemId::IntOutput(_) => DType::Int,
+        }
+    }
+}
+
+/// Value of any storage item
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Real(f64),
+    Bool(bool),
+    Int(i64),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Real(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Int(v) => write!(f, "{}", v),
+        }
+    }
 }
 
 impl<'a> From<&Parameter<'a, f64>> for SystemStorageItemId {
@@ -123,18 +445,42 @@ impl<'a> From<&Parameter<'a, bool>> for SystemStorageItemId {
     }
 }
 
+impl<'a> From<&Parameter<'a, i64>> for SystemStorageItemId {
+    fn from(x: &Parameter<'a, i64>) -> Self {
+        Self::IntParameter(x.id())
+    }
+}
+
 impl<'a> From<&ContinuousState<'a, f64>> for SystemStorageItemId {
     fn from(x: &ContinuousState<'a, f64>) -> Self {
         Self::RealState(x.id())
     }
 }
 
+impl<'a> From<&DiscreteState<'a, f64>> for SystemStorageItemId {
+    fn from(x: &DiscreteState<'a, f64>) -> Self {
+        Self::RealDiscreteState(x.id())
+    }
+}
+
 impl<'a> From<&DiscreteState<'a, bool>> for SystemStorageItemId {
     fn from(x: &DiscreteState<'a, bool>) -> Self {
         Self::BoolState(x.id())
     }
 }
 
+impl<'a> From<&DiscreteState<'a, i64>> for SystemStorageItemId {
+    fn from(x: &DiscreteState<'a, i64>) -> Self {
+        Self::IntState(x.id())
+    }
+}
+
+impl<'a, E: StateEnum> From<&DiscreteState<'a, E>> for SystemStorageItemId {
+    fn from(x: &DiscreteState<'a, E>) -> Self {
+        Self::IntState(x.id())
+    }
+}
+
 impl<'a> From<&Output<'a, f64>> for SystemStorageItemId {
     fn from(x: &Output<'a, f64>) -> Self {
         Self::RealOutput(x.id())
@@ -147,6 +493,43 @@ impl<'a> From<&Output<'a, bool>> for SystemStorageItemId {
     }
 }
 
+impl<'a> From<&Output<'a, i64>> for SystemStorageItemId {
+    fn from(x: &Output<'a, i64>) -> Self {
+        Self::IntOutput(x.id())
+    }
+}
+
+
+// Arrays are identified by their first element
+impl<'a, T: RealArray> From<&Parameter<'a, T>> for SystemStorageItemId {
+    fn from(x: &Parameter<'a, T>) -> Self {
+        Self::RealParameter(x.id())
+    }
+}
+
+impl<'a, T: RealArray> From<&ContinuousState<'a, T>> for SystemStorageItemId {
+    fn from(x: &ContinuousState<'a, T>) -> Self {
+        Self::RealState(x.id())
+    }
+}
+
+impl<'a, const N: usize> From<&DiscreteState<'a, [f64; N]>> for SystemStorageItemId {
+    fn from(x: &DiscreteState<'a, [f64; N]>) -> Self {
+        Self::RealDiscreteState(x.id())
+    }
+}
+
+impl<'a, const R: usize, const C: usize> From<&DiscreteState<'a, [[f64; C]; R]>> for SystemStorageItemId {
+    fn from(x: &DiscreteState<'a, [[f64; C]; R]>) -> Self {
+        Self::RealDiscreteState(x.id())
+    }
+}
+
+impl<'a, T: RealArray> From<&Output<'a, T>> for SystemStorageItemId {
+    fn from(x: &Output<'a, T>) -> Self {
+        Self::RealOutput(x.id())
+    }
+}
 
 #[derive(Default)]
 pub struct SystemStorageSelector {
@@ -157,10 +540,14 @@ pub struct SystemStorageSelector {
 pub struct SystemCounters {
     next_r_param: usize,
     next_b_param: usize,
+    next_i_param: usize,
     next_r_state: usize,
+    next_r_dstate: usize,
     next_b_state: usize,
+    next_i_state: usize,
     next_r_out: usize,
-    next_b_out: usize
+    next_b_out: usize,
+    next_i_out: usize,
 }
 
 
@@ -174,31 +561,75 @@ impl SystemCounters {
         self.next_r_param += 1;
         res
     }
+    /// Reserves `n` consecutive slots, e.g. for an array, and returns the first
+    pub fn next_r_param_n(&mut self, n: usize) -> usize {
+        let res = self.next_r_param;
+        self.next_r_param += n;
+        res
+    }
     pub fn next_b_param(&mut self) -> usize {
         let res = self.next_b_param;
         self.next_b_param += 1;
         res
     }
+    pub fn next_i_param(&mut self) -> usize {
+        let res = self.next_i_param;
+        self.next_i_param += 1;
+        res
+    }
     pub fn next_r_state(&mut self) -> usize {
         let res = self.next_r_state;
         self.next_r_state += 1;
         res
     }
+    /// Reserves `n` consecutive slots, e.g. for an array, and returns the first
+    pub fn next_r_state_n(&mut self, n: usize) -> usize {
+        let res = self.next_r_state;
+        self.next_r_state += n;
+        res
+    }
+    pub fn next_r_dstate(&mut self) -> usize {
+        let res = self.next_r_dstate;
+        self.next_r_dstate += 1;
+        res
+    }
+    /// Reserves `n` consecutive slots, e.g. for an array, and returns the first
+    pub fn next_r_dstate_n(&mut self, n: usize) -> usize {
+        let res = self.next_r_dstate;
+        self.next_r_dstate += n;
+        res
+    }
     pub fn next_b_state(&mut self) -> usize {
         let res = self.next_b_state;
         self.next_b_state += 1;
         res
     }
+    pub fn next_i_state(&mut self) -> usize {
+        let res = self.next_i_state;
+        self.next_i_state += 1;
+        res
+    }
     pub fn next_r_out(&mut self) -> usize {
         let res = self.next_r_out;
         self.next_r_out += 1;
         res
     }
+    /// Reserves `n` consecutive slots, e.g. for an array, and returns the first
+    pub fn next_r_out_n(&mut self, n: usize) -> usize {
+        let res = self.next_r_out;
+        self.next_r_out += n;
+        res
+    }
     pub fn next_b_out(&mut self) -> usize {
         let res = self.next_b_out;
         self.next_b_out += 1;
         res
     }
+    pub fn next_i_out(&mut self) -> usize {
+        let res = self.next_i_out;
+        self.next_i_out += 1;
+        res
+    }
   }
 
 
@@ -231,6 +662,15 @@ impl<'a, S: ISystem<'a>> Iterator for BlockIterator<'a, S> {
   
 }
 
+/// Parameter values restored by `ISystem::reset`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterReset {
+    /// Initial values of the blocks, then the overrides of `ISystem::init`
+    KeepOverrides,
+    /// Initial values of the blocks only
+    BlockInitials,
+}
+
 pub trait ISystem<'a> : Sized {
     const N_BLOCKS: usize;
 
@@ -238,31 +678,134 @@ pub trait ISystem<'a> : Sized {
 
     fn block(&'a self, i: usize) -> Option<&'a dyn IBlock>;
 
-    fn blocks(&self)-> BlockIterator<Self> {
+    /// Name of the block instance within the system, `Some` for every index `block` returns one for
+    fn block_name(&self, i: usize) -> Option<&'static str>;
+
+    fn blocks(&self)-> BlockIterator<'_, Self> {
         BlockIterator::<Self>::new(self)
     }
 
-    fn computations(&self) -> Vec<UpdateComputation>;
+    fn dependency_graph(&'a self) -> DependencyGraph<'a> {
+        DependencyGraph::new(
+            (0..).map_while(|i| Some((self.block_name(i)?, self.block(i)?))).collect()
+        )
+    }
+
+    /// Unconnected inputs as error, outputs nothing reads as warnings
+    fn validate(&'a self) -> Result<Vec<ConnectionIssue>, ValidationError> {
+        self.dependency_graph().validate()
+    }
+
+    /// Cycles of connections through blocks with direct feedthrough
+    fn algebraic_loops(&'a self) -> Vec<AlgebraicLoop> {
+        self.dependency_graph().algebraic_loops()
+    }
+
+    /// Block computations in execution order, derived from the connections between blocks
+    fn computations(&'a self) -> error::Result<Vec<ScheduledComputation<'a>>> {
+        schedule(self.dependency_graph()).map_err(Error::AlgebraicLoops)
+    }
   
-    fn step(&self, computations: &[UpdateComputation]) {
+    /// Runs all computations, regardless of their sample times
+    fn step(&self, computations: &[ScheduledComputation]) {
         for cmp in computations {
-            match cmp {
-                UpdateComputation::State(x) => x.f.apply(),
-                UpdateComputation::Output(x) => x.f.apply(),
-            }
+            cmp.apply();
+        }
+    }
+
+    /// Runs the computations with a sample hit at the given step
+    fn step_at(&self, computations: &[ScheduledComputation], step: StepKind) {
+        for cmp in computations.iter().filter(|cmp| cmp.runs_at(step)) {
+            cmp.apply();
         }
     }
 
-    fn advance_continuous_state(&self, dt: f64) {
+    /// Values of the zero-crossing functions of all blocks
+    fn zero_crossings(&'a self) -> Vec<f64> {
+        self.blocks().flat_map(|block| block.zero_crossings()).collect()
+    }
+
+    /// Recomputes outputs and state derivatives for the states currently in storage,
+    /// e.g. at the intermediate stages of an integrator. Only continuous blocks run and their
+    /// discrete state changes are discarded.
+    fn minor_step(&self, computations: &[ScheduledComputation]) {
         let storage = self.storage();
-        for i in 0..(storage.sizes().r_state) {
-            let old_value = storage.r_state_get(i);
-            let der = storage.r_state_der_get(i);
-            
-            // NOTE: This could be a different integrator
-            let new_value = old_value + der * dt;
-
-            storage.r_state_set(i, new_value);
+        let r_dstate: Vec<f64> = (0..storage.sizes().r_dstate)
+            .map(|i| storage.r_dstate_get(i))
+            .collect();
+        let b_state: Vec<bool> = (0..storage.sizes().b_state)
+            .map(|i| storage.b_state_get(i))
+            .collect();
+        let i_state: Vec<i64> = (0..storage.sizes().i_state)
+            .map(|i| storage.i_state_get(i))
+            .collect();
+        self.step_at(computations, StepKind::Minor);
+        for (i, value) in r_dstate.into_iter().enumerate() {
+            storage.r_dstate_set(i, value);
+        }
+        for (i, value) in b_state.into_iter().enumerate() {
+            storage.b_state_set(i, value);
+        }
+        for (i, value) in i_state.into_iter().enumerate() {
+            storage.i_state_set(i, value);
+        }
+    }
+
+    /// Advances the continuous states by `dt`. Expects the state derivatives in storage to be
+    /// up to date, i.e. `step` to have been called for the current states.
+    fn integrate<I: Integrator + ?Sized>(&self, integrator: &mut I, computations: &[ScheduledComputation], dt: f64) -> error::Result<()> {
+        integrator.advance(self.storage(), &mut || self.minor_step(computations), dt)
+    }
+
+    fn advance_continuous_state(&self, dt: f64) -> error::Result<()> {
+        self.integrate(&mut Euler, &[], dt)
+    }
+
+    /// Parameters, states and outputs of all blocks by path `block.field`
+    fn registry(&'a self) -> Registry {
+        let mut registry = Registry::new();
+        for (i, block) in self.blocks().enumerate() {
+            let block_name = self.block_name(i).expect("`block_name` must name every block of `block`");
+            for item in block.named_items() {
+                registry.insert(block_name, item);
+            }
+        }
+        registry
+    }
+
+    /// Overwrites the storage with the snapshot, like `SystemStorage::restore`, and checks that
+    /// the enum discrete states hold valid variants. The storage is left unchanged otherwise.
+    fn restore(&'a self, snapshot: &Snapshot) -> error::Result<()> {
+        let previous = self.storage().snapshot(snapshot.time());
+        self.storage().restore(snapshot)?;
+        let invalid: Vec<String> = (0..).map_while(|i| Some((self.block_name(i)?, self.block(i)?)))
+            .flat_map(|(block_name, block)| {
+                block.invalid_states().into_iter().map(move |name| format!("{}.{}", block_name, name))
+            })
+            .collect();
+        if !invalid.is_empty() {
+            self.storage().restore(&previous)?;
+            return Err(Error::InvalidStates(invalid));
+        }
+        Ok(())
+    }
+
+    /// Parameter values of the system overriding the initial values of its blocks
+    fn init(&self) {}
+
+    /// Restores the initial states of all blocks and their parameters as selected by `parameters`,
+    /// e.g. to run another scenario without rebuilding the system
+    fn reset(&'a self, parameters: ParameterReset) {
+        for block in self.blocks() {
+            for value in block.initial_values() {
+                match value {
+                    InitialValue::Parameter(handle) => handle.reset(),
+                    InitialValue::State(handle) => handle.reset(),
+                }
+            }
+        }
+        if parameters == ParameterReset::KeepOverrides {
+            self.init();
         }
     }
 }
@@ -271,105 +814,125 @@ pub trait ISystem<'a> : Sized {
 macro_rules! static_storage {
     ($ident: ident, $($block_size: path),+) => {        
         pub mod $ident {
-            use dataflow_core::system::{SystemSize, SystemStorage};
+            use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+            use dataflow_core::system::{SystemSize, SystemStorage, AtomicF64};
             use super::*;
             pub struct StorageFacade;
 
-            pub const fn create_storage() -> StorageFacade {
-                const SS: SystemSize = SystemSize::new()
-                    $(.add($block_size))+;
-                    // .add(thermal_mass::SIZE)
-                    // .add(hysteresis::SIZE)
-                    // .add(converter_b2f::SIZE);
-              
-                struct StorageImpl {
-                  r_param: [f64; SS.r_param],
-                  b_param: [bool; SS.b_param],
-                  r_state: [f64; SS.r_state],
-                  r_state_der: [f64; SS.r_state],
-                  b_state: [bool; SS.b_state],
-                  r_out: [f64; SS.r_out],
-                  b_out: [bool; SS.b_out],
-                }
-              
-                static mut STORAGE: StorageImpl = StorageImpl {
-                  r_param: [0.0; SS.r_param],
-                  b_param: [false; SS.b_param],
-                  r_state: [0.0; SS.r_state],
-                  r_state_der: [0.0; SS.r_state],
-                  b_state: [false; SS.b_state],
-                  r_out: [0.0; SS.r_out],
-                  b_out: [false; SS.b_out],
-                };
-              
-              
-              
-                impl SystemStorage for StorageFacade {
-                  fn sizes(&self) -> SystemSize {
-                    SS
-                  }
-              
-                  fn r_param_get(&self, ind: usize) -> &f64 {
-                    unsafe { &STORAGE.r_param[ind] }
-                  }
-                  fn r_param_set(&self, ind: usize, value: f64) {
-                    unsafe { STORAGE.r_param[ind] = value }
-                  }
-              
-                  fn b_param_get(&self, ind: usize) -> &bool {
-                    unsafe {&STORAGE.b_param[ind]}
-                  }
-                  fn b_param_set(&self, ind: usize, value: bool) {
-                    unsafe {STORAGE.b_param[ind] = value}
-              
-                  }
-                  
-                  fn r_state_get(&self, ind: usize) -> &f64 {
-                    unsafe {&STORAGE.r_state[ind]}
-              
-                  }
-                  fn r_state_set(&self, ind: usize, value: f64) {
-                    unsafe {STORAGE.r_state[ind] = value}
-              
-                  }
-                  fn r_state_der_get(&self, ind: usize) -> &f64 {
-                    unsafe {&STORAGE.r_state_der[ind]}
-              
-                  }
-                  fn r_state_der_set(&self, ind: usize, value: f64) {
-                    unsafe {STORAGE.r_state_der[ind] = value}
-              
-                  }
-                  
-                  fn b_state_get(&self, ind: usize) -> &bool {
-                    unsafe {&STORAGE.b_state[ind]}
-              
-                  }
-                  fn b_state_set(&self, ind: usize, value: bool) {
-                    unsafe {STORAGE.b_state[ind] = value}
-              
-                  }
-              
-                  fn r_out_get(&self, ind: usize) -> &f64 {
-                    unsafe {&STORAGE.r_out[ind]}
-              
-                  }
-                  fn r_out_set(&self, ind: usize, value: f64) {
-                    unsafe {STORAGE.r_out[ind] = value}
-              
-                  }
-                  
-                  fn b_out_get(&self, ind: usize) -> &bool {
-                    unsafe {&STORAGE.b_out[ind]}
-              
-                  }
-                  fn b_out_set(&self, ind: usize, value: bool) {
-                    unsafe {STORAGE.b_out[ind] = value}
+            const SS: SystemSize = SystemSize::new()
+                $(.add($block_size))+;
+          
+            struct StorageImpl {
+              r_param: [AtomicF64; SS.r_param],
+              b_param: [AtomicBool; SS.b_param],
+              i_param: [AtomicI64; SS.i_param],
+              r_state: [AtomicF64; SS.r_state],
+              r_state_der: [AtomicF64; SS.r_state],
+              r_dstate: [AtomicF64; SS.r_dstate],
+              b_state: [AtomicBool; SS.b_state],
+              i_state: [AtomicI64; SS.i_state],
+              r_out: [AtomicF64; SS.r_out],
+              b_out: [AtomicBool; SS.b_out],
+              i_out: [AtomicI64; SS.i_out],
+            }
+          
+            static STORAGE: StorageImpl = StorageImpl {
+              r_param: [const { AtomicF64::new(0.0) }; SS.r_param],
+              b_param: [const { AtomicBool::new(false) }; SS.b_param],
+              i_param: [const { AtomicI64::new(0) }; SS.i_param],
+              r_state: [const { AtomicF64::new(0.0) }; SS.r_state],
+              r_state_der: [const { AtomicF64::new(0.0) }; SS.r_state],
+              r_dstate: [const { AtomicF64::new(0.0) }; SS.r_dstate],
+              b_state: [const { AtomicBool::new(false) }; SS.b_state],
+              i_state: [const { AtomicI64::new(0) }; SS.i_state],
+              r_out: [const { AtomicF64::new(0.0) }; SS.r_out],
+              b_out: [const { AtomicBool::new(false) }; SS.b_out],
+              i_out: [const { AtomicI64::new(0) }; SS.i_out],
+            };
+          
+            impl SystemStorage for StorageFacade {
+              fn sizes(&self) -> SystemSize {
+                SS
+              }
+          
+              fn r_param_get(&self, ind: usize) -> f64 {
+                STORAGE.r_param[ind].get()
+              }
+              fn r_param_set(&self, ind: usize, value: f64) {
+                STORAGE.r_param[ind].set(value)
+              }
+          
+              fn b_param_get(&self, ind: usize) -> bool {
+                STORAGE.b_param[ind].load(Ordering::Relaxed)
+              }
+              fn b_param_set(&self, ind: usize, value: bool) {
+                STORAGE.b_param[ind].store(value, Ordering::Relaxed)
+              }
+
+              fn i_param_get(&self, ind: usize) -> i64 {
+                STORAGE.i_param[ind].load(Ordering::Relaxed)
+              }
+              fn i_param_set(&self, ind: usize, value: i64) {
+                STORAGE.i_param[ind].store(value, Ordering::Relaxed)
+              }
               
-                  }
+              fn r_state_get(&self, ind: usize) -> f64 {
+                STORAGE.r_state[ind].get()
+              }
+              fn r_state_set(&self, ind: usize, value: f64) {
+                STORAGE.r_state[ind].set(value)
+              }
+              fn r_state_der_get(&self, ind: usize) -> f64 {
+                STORAGE.r_state_der[ind].get()
+              }
+              fn r_state_der_set(&self, ind: usize, value: f64) {
+                STORAGE.r_state_der[ind].set(value)
+              }
+
+              fn r_dstate_get(&self, ind: usize) -> f64 {
+                STORAGE.r_dstate[ind].get()
+              }
+              fn r_dstate_set(&self, ind: usize, value: f64) {
+                STORAGE.r_dstate[ind].set(value)
+              }
               
-                }
+              fn b_state_get(&self, ind: usize) -> bool {
+                STORAGE.b_state[ind].load(Ordering::Relaxed)
+              }
+              fn b_state_set(&self, ind: usize, value: bool) {
+                STORAGE.b_state[ind].store(value, Ordering::Relaxed)
+              }
+
+              fn i_state_get(&self, ind: usize) -> i64 {
+                STORAGE.i_state[ind].load(Ordering::Relaxed)
+              }
+              fn i_state_set(&self, ind: usize, value: i64) {
+                STORAGE.i_state[ind].store(value, Ordering::Relaxed)
+              }
+          
+              fn r_out_get(&self, ind: usize) -> f64 {
+                STORAGE.r_out[ind].get()
+              }
+              fn r_out_set(&self, ind: usize, value: f64) {
+                STORAGE.r_out[ind].set(value)
+              }
               
+              fn b_out_get(&self, ind: usize) -> bool {
+                STORAGE.b_out[ind].load(Ordering::Relaxed)
+              }
+              fn b_out_set(&self, ind: usize, value: bool) {
+                STORAGE.b_out[ind].store(value, Ordering::Relaxed)
+              }
+
+              fn i_out_get(&self, ind: usize) -> i64 {
+                STORAGE.i_out[ind].load(Ordering::Relaxed)
+              }
+              fn i_out_set(&self, ind: usize, value: i64) {
+                STORAGE.i_out[ind].store(value, Ordering::Relaxed)
+              }
+            }
+
+            pub const fn create_storage() -> StorageFacade {
                 StorageFacade
             }
             const FACADE: StorageFacade = create_storage();
@@ -1,68 +1,73 @@
-use crate::system::{SystemStorage, SystemStorageSelector, SystemStorageItemId};
+use crate::system::{SystemStorage, SystemStorageSelector, SystemStorageItemId, Value};
+use crate::block::{DiscreteState, StateEnum};
+use crate::error::Result;
+use std::collections::HashMap;
 use csv;
 
 pub struct CoreWriter {
   
 }
 
+pub type WriterResult<T> = Result<T>;
+
 pub trait IWriter {
-  type Res<T>;
-  fn add_field<T: Into<SystemStorageItemId>>(&mut self, field: T);
-  fn write_step(&mut self, storage: &dyn SystemStorage, time: f64) -> Self::Res<()>;
+  fn add_field<T: Into<SystemStorageItemId>>(&mut self, field: T) where Self: Sized;
+  /// Adds one field per item, e.g. per element of an array signal
+  fn add_fields<I: IntoIterator<Item = SystemStorageItemId>>(&mut self, items: I) where Self: Sized {
+    for item in items {
+      self.add_field(item);
+    }
+  }
+  /// Adds an enum state, written by variant name where the writer supports it
+  fn add_enum_field<E: StateEnum>(&mut self, state: &DiscreteState<'_, E>) where Self: Sized {
+    self.add_field(state);
+  }
+  fn write_step(&mut self, storage: &dyn SystemStorage, time: f64) -> WriterResult<()>;
 }
 
 use std::fs::File;
 
 pub struct CSVWriter {
   writer: csv::Writer<File>,
-  selector: SystemStorageSelector
+  selector: SystemStorageSelector,
+  /// Variant names of the enum states, by integer state index
+  variant_names: HashMap<usize, fn(i64) -> Option<&'static str>>,
 }
 
 impl CSVWriter {
-  pub fn new(path: &str) -> CSVWriter {
-    let writer = csv::Writer::from_path(path).unwrap();
+  pub fn new(path: &str) -> Result<CSVWriter> {
+    let writer = csv::Writer::from_path(path)?;
 
-    CSVWriter {writer, selector: SystemStorageSelector::default()}
+    Ok(CSVWriter {writer, selector: SystemStorageSelector::default(), variant_names: HashMap::new()})
   }
 }
 
 impl IWriter for CSVWriter {
-  type Res<T> = csv::Result<T>;
   fn add_field<T: Into<SystemStorageItemId>>(&mut self, field: T) {
     self.selector.items.push(field.into());
   }
 
-  fn write_step(&mut self, storage: &dyn SystemStorage, time: f64) -> Self::Res<()> {
-    println!("=========== Writing step ===========");
+  fn add_enum_field<E: StateEnum>(&mut self, state: &DiscreteState<'_, E>) {
+    self.variant_names.insert(state.id(), E::variant_name);
+    self.add_field(state);
+  }
+
+  fn write_step(&mut self, storage: &dyn SystemStorage, time: f64) -> WriterResult<()> {
+    for item in &self.selector.items {
+      storage.check(*item)?;
+    }
     self.writer.write_field(time.to_string())?;
-    self.selector.items.iter().map(|item|
-      match item {
-        crate::system::SystemStorageItemId::RealParameter(ind) => {
-          let value = storage.r_param_get(*ind);
-          self.writer.write_field(value.to_string())
-        },
-        crate::system::SystemStorageItemId::BoolParameter(ind) => {
-          let value = storage.b_param_get(*ind);
-          self.writer.write_field(value.to_string())
-        },
-        crate::system::SystemStorageItemId::RealState(ind) => {
-          let value = storage.r_state_get(*ind);
-          self.writer.write_field(value.to_string())          
-        },
-        crate::system::SystemStorageItemId::BoolState(ind) => {
-          let value = storage.b_state_get(*ind);
-          self.writer.write_field(value.to_string())          
-        },
-        crate::system::SystemStorageItemId::RealOutput(ind) => {
-          let value = storage.r_out_get(*ind);
-          self.writer.write_field(value.to_string())          
-        },
-        crate::system::SystemStorageItemId::BoolOutput(ind) => {
-          let value = storage.b_out_get(*ind);
-          self.writer.write_field(value.to_string())          
-        },
-      }  
-    ).collect::<csv::Result<()>>()?;
+    self.selector.items.iter().map(|item| {
+      let value = storage.value(*item);
+      let variant_name = match (item, value) {
+        (SystemStorageItemId::IntState(ind), Value::Int(v)) => self.variant_names.get(ind).and_then(|name| name(v)),
+        _ => None,
+      };
+      match variant_name {
+        Some(name) => self.writer.write_field(name),
+        None => self.writer.write_field(value.to_string()),
+      }
+    }).collect::<csv::Result<()>>()?;
     self.writer.write_record(None::<&[u8]>)?;
     self.writer.flush()?;
     Ok(())
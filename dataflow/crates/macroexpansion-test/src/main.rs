@@ -9,29 +9,93 @@ struct ThermalMass<'a> {
   pub t: ContinuousState<'a, f64>,
   pub t_out: Output<'a, f64>,
 }
-impl<'a> ThermalMass<'a> {
-  pub fn new(
-      storage: &'a dyn dataflow_core::system::SystemStorage,
-      counters: &mut dataflow_core::system::SystemCounters,
-  ) -> ThermalMass<'a> {
-      ThermalMass {
-          cp: "4",
-          h: "4",
-          area: "4",
-          t_amb: "4",
-      }
+pub fn new<'a>(
+  storage: &'a dyn dataflow_core::system::SystemStorage,
+  counters: &mut dataflow_core::system::SystemCounters,
+) -> ThermalMass<'a> {
+  ThermalMass {
+    cp: dataflow_core::block::Initial::init(dataflow_core::block::Parameter::new(storage, counters.next_r_param()), 0.0),
+    h: dataflow_core::block::Initial::init(dataflow_core::block::Parameter::new(storage, counters.next_r_param()), 0.0),
+    area: dataflow_core::block::Initial::init(dataflow_core::block::Parameter::new(storage, counters.next_r_param()), 0.0),
+    t_amb: dataflow_core::block::Initial::init(dataflow_core::block::Parameter::new(storage, counters.next_r_param()), 0.0),
+    t: dataflow_core::block::Initial::init(dataflow_core::block::ContinuousState::new(storage, counters.next_r_state()), 0.0),
+    qdot: dataflow_core::block::Input::new(storage),
+    t_out: dataflow_core::block::Output::new(storage, counters.next_r_out()),
   }
 }
 pub const SIZE: dataflow_core::block::BlockSize = dataflow_core::block::BlockSize {
   r_param: 4usize,
   b_param: 0usize,
+  i_param: 0usize,
   r_state: 1usize,
+  r_dstate: 0usize,
   b_state: 0usize,
+  i_state: 0usize,
   r_in: 1usize,
   b_in: 0usize,
+  i_in: 0usize,
   r_out: 1usize,
   b_out: 0usize,
+  i_out: 0usize,
 };
+pub const FEEDTHROUGH: Option<&[dataflow_core::block::Feedthrough]> = None;
+pub struct OutputUpdate {
+  pub t_out: f64,
+}
+pub struct StateUpdate {
+  pub t_dot: f64,
+  pub t: Option<f64>,
+}
+impl<'a> dataflow_core::block::BlockFields for ThermalMass<'a> {
+  type OutputUpdate = OutputUpdate;
+  type StateUpdate = StateUpdate;
+
+  const FEEDTHROUGH: Option<&'static [dataflow_core::block::Feedthrough]> = FEEDTHROUGH;
+
+  fn write_outputs(&self, update: OutputUpdate) {
+    dataflow_core::block::Access::set(&self.t_out, update.t_out);
+  }
+
+  fn write_states(&self, update: StateUpdate) {
+    dataflow_core::block::DerivativeAccess::der_set(&self.t, update.t_dot);
+    if let Some(x) = update.t {
+      dataflow_core::block::Access::set(&self.t, x)
+    }
+  }
+
+  fn port_list(&self) -> (Vec<dataflow_core::block::InputPort>, Vec<dataflow_core::block::OutputPort>) {
+    (
+      vec![dataflow_core::block::InputPort::new("qdot", self.qdot.source())],
+      vec![dataflow_core::block::OutputPort::new("t_out", (&self.t_out).into())],
+    )
+  }
+
+  fn field_items(&self) -> Vec<dataflow_core::block::NamedItem> {
+    vec![
+      dataflow_core::block::NamedItem::new("cp", &self.cp),
+      dataflow_core::block::NamedItem::new("h", &self.h),
+      dataflow_core::block::NamedItem::new("area", &self.area),
+      dataflow_core::block::NamedItem::new("t_amb", &self.t_amb),
+      dataflow_core::block::NamedItem::new("t", &self.t),
+      dataflow_core::block::NamedItem::new("t_out", &self.t_out),
+    ]
+  }
+
+  fn field_initial_values(&self) -> Vec<dataflow_core::block::InitialValue<'_>> {
+    vec![
+      dataflow_core::block::InitialValue::Parameter(&self.cp),
+      dataflow_core::block::InitialValue::Parameter(&self.h),
+      dataflow_core::block::InitialValue::Parameter(&self.area),
+      dataflow_core::block::InitialValue::Parameter(&self.t_amb),
+      dataflow_core::block::InitialValue::State(&self.t),
+    ]
+  }
+
+  fn field_invalid_states(&self) -> Vec<&'static str> {
+    let states: Vec<Option<&'static str>> = vec![];
+    states.into_iter().flatten().collect()
+  }
+}
 
 
 fn main() {
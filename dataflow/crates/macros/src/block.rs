@@ -1,7 +1,8 @@
 use proc_macro2::TokenStream as TokenStream2;
 use syn::{DeriveInput};
 use syn::spanned::Spanned;
-use quote::{quote};
+use quote::{quote, format_ident};
+use syn::parse::Parser;
 use dataflow_reflect::block as rblock;
 use dataflow_reflect::block::AttachTo;
 use std::marker::PhantomData;
@@ -16,6 +17,10 @@ pub fn create_block_repr(ast: &DeriveInput) -> Res<rblock::Block> {
   let block_name = &ast.ident.to_string();
   let mut block_repr = rblock::Block::new(block_name.clone());
   block_repr.span = Some(ast.span());
+  block_repr.lifetime = match ast.generics.lifetimes().next() {
+    Some(def) => Some(def.lifetime.clone()),
+    None => cerror!(ast, "Block definition must have a lifetime parameter for its storage, e.g. `Block<'a>`")
+  };
   let fields = match ast.data {
     syn::Data::Struct(syn::DataStruct{
       fields: syn::Fields::Named(syn::FieldsNamed {
@@ -28,6 +33,7 @@ pub fn create_block_repr(ast: &DeriveInput) -> Res<rblock::Block> {
   for field in fields.iter() {
     create_field_repr(&mut block_repr, field)?;
   }
+  check_feedthrough(&block_repr)?;
 
   // eprintln!("{:#?}", block_repr);
   Ok(block_repr)
@@ -44,25 +50,40 @@ fn create_field_repr(block_repr: &mut rblock::Block, field: &syn::Field) -> Res<
   };
 
   let dtype = if field_type.type_params.len() == 1 {
-    get_dtype(field_type.type_params.first().unwrap())?
+    get_dtype(field_type.type_params.first().unwrap(), &btype)?
   } else {
     cerror!(field, "too many type parameters ({}) for field {}",
       field_type.type_params.len(), field_ident
     )
   };
+  let shape = field_type.type_params[0].shape.clone();
+  if !shape.is_empty() && !matches!(dtype, rblock::DType::Real) {
+    cerror!(field, "only arrays of f64 are supported")
+  }
+  let init = parse_init(field, &btype, &field_type.type_params[0])?;
   let block_field = rblock::BlockField {
     name: field_ident.to_string(),
     span: Some(field.span()),
-    dtype: dtype.clone(), btype: btype.clone()
+    dtype: dtype.clone(), btype: btype.clone(),
+    shape,
+    value_type: value_type(&field.ty),
+    init,
   };
   // eprintln!("{:?}", block_field);
+  let feedthrough = parse_feedthrough(field)?;
+  if feedthrough.is_some() && !matches!(btype, rblock::BlockFieldType::Output) {
+    cerror!(field, "feedthrough can only be declared on outputs")
+  }
   match btype {
     rblock::BlockFieldType::Parameter => match dtype {
       rblock::DType::Real => rblock::Parameter::<f64> {
-        field: block_field, default: Some(0.0)
+        field: block_field, _marker: PhantomData
       }.attach_to(block_repr),
       rblock::DType::Bool => rblock::Parameter::<bool> {
-        field: block_field, default: Some(false)
+        field: block_field, _marker: PhantomData
+      }.attach_to(block_repr),
+      rblock::DType::Int => rblock::Parameter::<i64> {
+        field: block_field, _marker: PhantomData
       }.attach_to(block_repr),
     },
     rblock::BlockFieldType::Input => match dtype {
@@ -72,28 +93,37 @@ fn create_field_repr(block_repr: &mut rblock::Block, field: &syn::Field) -> Res<
       rblock::DType::Bool => rblock::Input::<bool> {
         field: block_field, _marker: PhantomData
       }.attach_to(block_repr), 
+      rblock::DType::Int => rblock::Input::<i64> {
+        field: block_field, _marker: PhantomData
+      }.attach_to(block_repr),
     },
     rblock::BlockFieldType::Output => match dtype {
       rblock::DType::Real => rblock::Output::<f64> {
-        field: block_field, _marker: PhantomData
+        field: block_field, feedthrough, _marker: PhantomData
       }.attach_to(block_repr),
       rblock::DType::Bool => rblock::Output::<bool> {
-        field: block_field, _marker: PhantomData
+        field: block_field, feedthrough, _marker: PhantomData
+      }.attach_to(block_repr),
+      rblock::DType::Int => rblock::Output::<i64> {
+        field: block_field, feedthrough, _marker: PhantomData
       }.attach_to(block_repr),
     },
     rblock::BlockFieldType::DiscreteState => match dtype {
-      rblock::DType::Real => cerror!(
-        field, "Discrete state cannot be of type f64"
-      ),
+      rblock::DType::Real => block_repr.r_dstate.push(rblock::State::<f64> {
+        field: block_field, _marker: PhantomData
+      }),
       rblock::DType::Bool => rblock::State::<bool> {
-        field: block_field, initial: false
+        field: block_field, _marker: PhantomData
+      }.attach_to(block_repr),
+      rblock::DType::Int => rblock::State::<i64> {
+        field: block_field, _marker: PhantomData
       }.attach_to(block_repr),
     },
     rblock::BlockFieldType::ContinuousState => match dtype {
       rblock::DType::Real => rblock::State::<f64> {
-        field: block_field, initial: 0.0
+        field: block_field, _marker: PhantomData
       }.attach_to(block_repr),
-      rblock::DType::Bool => cerror!(
+      rblock::DType::Bool | rblock::DType::Int => cerror!(
         field, "Continuous state should be of type f64"
       ),
     },
@@ -102,86 +132,469 @@ fn create_field_repr(block_repr: &mut rblock::Block, field: &syn::Field) -> Res<
   Ok(())
 }
 
-fn get_dtype(tpe: &SimpleType) -> Res<rblock::DType> {
+/// Parses `#[feedthrough(in1, in2)]`
+fn parse_feedthrough(field: &syn::Field) -> Res<Option<Vec<syn::Ident>>> {
+  let attr = match field.attrs.iter().find(|attr| attr.path.is_ident("feedthrough")) {
+    Some(attr) => attr,
+    None => return Ok(None),
+  };
+  let nested = match attr.parse_meta()? {
+    syn::Meta::List(syn::MetaList {nested, ..}) => nested,
+    meta => cerror!(meta, "expected list of inputs, e.g. #[feedthrough(in1, in2)]"),
+  };
+  nested.iter().map(|item| match item {
+    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.get_ident().is_some() => 
+      Ok(path.get_ident().unwrap().to_owned()),
+    _ => lerror!(item, "expected input name"),
+  }).collect::<Res<Vec<syn::Ident>>>().map(Some)
+}
+
+/// Parses `#[default = literal]` on parameters and `#[initial = literal]` on states, or
+/// `#[default(expr)]` and `#[initial(expr)]` for other values, e.g. arrays and enum variants.
+/// Without them, fields are initialised with the zero of their dtype, except enum states.
+fn parse_init(field: &syn::Field, btype: &rblock::BlockFieldType, tpe: &SimpleType) -> Res<Option<TokenStream2>> {
+  let expected = match btype {
+    rblock::BlockFieldType::Parameter => Some("default"),
+    rblock::BlockFieldType::ContinuousState | rblock::BlockFieldType::DiscreteState => Some("initial"),
+    rblock::BlockFieldType::Input | rblock::BlockFieldType::Output => None,
+  };
+  let mut init = None;
+  for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("default") || attr.path.is_ident("initial")) {
+    let name = attr.path.get_ident().unwrap().to_string();
+    if expected != Some(name.as_str()) {
+      cerror!(attr, "#[default] only applies to parameters and #[initial] to states")
+    }
+    if init.is_some() {
+      cerror!(attr, "duplicate #[{}]", name)
+    }
+    // The value is passed on as is, the compiler checks it against the field type
+    let parser = |input: syn::parse::ParseStream| {
+      let value = if input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in input);
+        content.parse::<TokenStream2>()?
+      } else {
+        input.parse::<syn::Token![=]>()?;
+        input.parse::<TokenStream2>()?
+      };
+      if value.is_empty() {
+        return Err(input.error("expected a value"))
+      }
+      Ok(value)
+    };
+    init = Some(parser.parse2(attr.tokens.clone())?);
+  }
+  if init.is_none() && expected.is_some() {
+    init = if !tpe.shape.is_empty() {
+      Some(quote!(::core::default::Default::default()))
+    } else {
+      match tpe.qualified_path.last().unwrap().to_string().as_str() {
+        "f64" => Some(quote!(0.0)),
+        "bool" => Some(quote!(false)),
+        "i64" => Some(quote!(0)),
+        _ => None,
+      }
+    };
+  }
+  Ok(init)
+}
+
+/// `T` of a handle type like `Parameter<'a, T>`
+fn value_type(ty: &syn::Type) -> Option<TokenStream2> {
+  let segment = match ty {
+    syn::Type::Path(syn::TypePath { path, .. }) => path.segments.last()?,
+    _ => return None,
+  };
+  match &segment.arguments {
+    syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+      syn::GenericArgument::Type(tpe) => Some(quote!(#tpe)),
+      _ => None,
+    }),
+    _ => None,
+  }
+}
+
+fn check_feedthrough(block_repr: &rblock::Block) -> Res<()> {
+  let inputs = block_repr.input_names();
+  let declared = block_repr.r_out.iter().filter_map(|x| x.feedthrough.as_ref())
+    .chain(block_repr.b_out.iter().filter_map(|x| x.feedthrough.as_ref()))
+    .chain(block_repr.i_out.iter().filter_map(|x| x.feedthrough.as_ref()));
+  for input in declared.flatten() {
+    if !inputs.iter().any(|name| *input == name) {
+      cerror!(input, "`{}` is not an input of the block", input)
+    }
+  }
+  Ok(())
+}
+
+fn get_dtype(tpe: &SimpleType, btype: &rblock::BlockFieldType) -> Res<rblock::DType> {
   let dtype_ident = tpe.qualified_path.last().unwrap();
   match dtype_ident.to_string().as_str() {
     "f64" => Ok(rblock::DType::Real),
     "bool" => Ok(rblock::DType::Bool),
+    "i64" => Ok(rblock::DType::Int),
+    // Any other discrete state is a `StateEnum`, stored in the integer lane
+    _ if tpe.shape.is_empty() && matches!(btype, rblock::BlockFieldType::DiscreteState) =>
+      Ok(rblock::DType::Int),
     x => cerror!(dtype_ident, "Unknown field dtype {}", x)
   }
 }
 
 
-// dataflow_core::system::SystemStorage
 pub fn generate_new(block_repr: &rblock::Block) -> TokenStream2 {
   let block_ident = syn::Ident::new(
     &block_repr.name, block_repr.span.unwrap()
   );
-  let field_expr = block_repr.r_param.iter().map(
-    |f| {
-      let f_name = syn::Ident::new(&f.field.name, f.field.span.unwrap());
-      quote!(#f_name: "4")
-    }
-  ); 
-    // #(#field_expr),*
-  // cp: Parameter::<f64>::new(storage, counters.next_r_param()),
-  // h: Parameter::<f64>::new(storage, counters.next_r_param()),
-  // area: Parameter::<f64>::new(storage, counters.next_r_param()),
-  // t_amb: Parameter::<f64>::new(storage, counters.next_r_param()),
-  // t: ContinuousState::new(storage, counters.next_r_state()),
-  // qdot: Input::new(storage),
-  // t_out: Output::new(storage, counters.next_r_out()),
+  let lifetime = &block_repr.lifetime;
+  let fields = block_repr.r_param.iter().map(|x| generate_field_new(&x.field, "r_param"))
+    .chain(block_repr.b_param.iter().map(|x| generate_field_new(&x.field, "b_param")))
+    .chain(block_repr.i_param.iter().map(|x| generate_field_new(&x.field, "i_param")))
+    .chain(block_repr.r_state.iter().map(|x| generate_field_new(&x.field, "r_state")))
+    .chain(block_repr.r_dstate.iter().map(|x| generate_field_new(&x.field, "r_dstate")))
+    .chain(block_repr.b_state.iter().map(|x| generate_field_new(&x.field, "b_state")))
+    .chain(block_repr.i_state.iter().map(|x| generate_field_new(&x.field, "i_state")))
+    .chain(block_repr.r_in.iter().map(|x| generate_field_new(&x.field, "r_in")))
+    .chain(block_repr.b_in.iter().map(|x| generate_field_new(&x.field, "b_in")))
+    .chain(block_repr.i_in.iter().map(|x| generate_field_new(&x.field, "i_in")))
+    .chain(block_repr.r_out.iter().map(|x| generate_field_new(&x.field, "r_out")))
+    .chain(block_repr.b_out.iter().map(|x| generate_field_new(&x.field, "b_out")))
+    .chain(block_repr.i_out.iter().map(|x| generate_field_new(&x.field, "i_out")));
 
   quote!{
-    impl #block_ident {
-      pub fn new<'a>(
-        storage: &'a dyn dataflow_core::system::SystemStorage, 
-        counters: &mut dataflow_core::system::SystemCounters
-      ) -> #block_ident<'a> {
-        #block_ident {
-          #(#field_expr),* ,
-        }
+    pub fn new<#lifetime>(
+      storage: &#lifetime dyn dataflow_core::system::SystemStorage,
+      counters: &mut dataflow_core::system::SystemCounters
+    ) -> #block_ident<#lifetime> {
+      #block_ident {
+        #(#fields),*
       }
-    }    
+    }
   }
 }
 
-fn generate_field_new(field: &rblock::BlockField) -> TokenStream2 {
+/// `field: Handle::new(storage, counters.next_<lane>())`, with `.init(..)` if the field has an initial value
+fn generate_field_new(field: &rblock::BlockField, lane: &str) -> TokenStream2 {
   let field_ident = syn::Ident::new(&field.name, field.span.unwrap());
+  let next = if field.shape.is_empty() {
+    let next = format_ident!("next_{}", lane);
+    quote!(counters.#next())
+  } else {
+    let next = format_ident!("next_{}_n", lane);
+    let len = field.slots();
+    quote!(counters.#next(#len))
+  };
+  let handle = match field.btype {
+    rblock::BlockFieldType::Parameter => quote!(dataflow_core::block::Parameter::new(storage, #next)),
+    rblock::BlockFieldType::Input => quote!(dataflow_core::block::Input::new(storage)),
+    rblock::BlockFieldType::Output => quote!(dataflow_core::block::Output::new(storage, #next)),
+    rblock::BlockFieldType::ContinuousState => quote!(dataflow_core::block::ContinuousState::new(storage, #next)),
+    rblock::BlockFieldType::DiscreteState => quote!(dataflow_core::block::DiscreteState::new(storage, #next)),
+  };
+  match &field.init {
+    Some(init) => quote!(#field_ident: dataflow_core::block::Initial::init(#handle, #init)),
+    None => quote!(#field_ident: #handle),
+  }
+}
 
+pub fn generate_feedthrough(block_repr: &rblock::Block) -> TokenStream2 {
+  let outputs: Vec<(&String, &Option<Vec<syn::Ident>>)> = block_repr.r_out.iter()
+    .map(|x| (&x.field.name, &x.feedthrough))
+    .chain(block_repr.b_out.iter().map(|x| (&x.field.name, &x.feedthrough)))
+    .chain(block_repr.i_out.iter().map(|x| (&x.field.name, &x.feedthrough)))
+    .collect();
+
+  if outputs.iter().all(|(_, feedthrough)| feedthrough.is_none()) {
+    return quote!{
+      pub const FEEDTHROUGH: Option<&[dataflow_core::block::Feedthrough]> = None;
+    }
+  }
+
+  let all_inputs = block_repr.input_names();
+  let items = outputs.iter().map(|(name, feedthrough)| {
+    let inputs: Vec<String> = match feedthrough {
+      Some(inputs) => inputs.iter().map(|x| x.to_string()).collect(),
+      None => all_inputs.iter().map(|x| x.to_string()).collect(),
+    };
+    quote!{
+      dataflow_core::block::Feedthrough { output: #name, inputs: &[#(#inputs),*] }
+    }
+  });
   quote!{
-    #field_ident: Parameter::<f64>::new(storage, counters.next_r_param())
+    pub const FEEDTHROUGH: Option<&[dataflow_core::block::Feedthrough]> = Some(&[#(#items),*]);
   }
 }
 
 pub fn generate_size(block_repr: &rblock::Block) -> TokenStream2 {
-  let r_param = block_repr.r_param.len();
+  fn slots<T>(fields: &[T], field: fn(&T) -> &rblock::BlockField) -> usize {
+    fields.iter().map(|x| field(x).slots()).sum()
+  }
+
+  let r_param = slots(&block_repr.r_param, |x| &x.field);
   let b_param = block_repr.b_param.len();
+  let i_param = block_repr.i_param.len();
 
-  let r_state = block_repr.r_state.len();
+  let r_state = slots(&block_repr.r_state, |x| &x.field);
+  let r_dstate = slots(&block_repr.r_dstate, |x| &x.field);
   let b_state = block_repr.b_state.len();
+  let i_state = block_repr.i_state.len();
 
-  let r_in = block_repr.r_in.len();
+  let r_in = slots(&block_repr.r_in, |x| &x.field);
   let b_in = block_repr.b_in.len();
+  let i_in = block_repr.i_in.len();
 
-  let r_out = block_repr.r_out.len();
+  let r_out = slots(&block_repr.r_out, |x| &x.field);
   let b_out = block_repr.b_out.len();
+  let i_out = block_repr.i_out.len();
 
   quote!{
     pub const SIZE: dataflow_core::block::BlockSize = dataflow_core::block::BlockSize {
       r_param: #r_param,
       b_param: #b_param,
+      i_param: #i_param,
     
       r_state: #r_state,
+      r_dstate: #r_dstate,
       b_state: #b_state,
+      i_state: #i_state,
     
       r_in: #r_in,
       b_in: #b_in,
+      i_in: #i_in,
     
       r_out: #r_out,
       b_out: #b_out,    
+      i_out: #i_out,
     };
   }
 
 
-}
\ No newline at end of file
+}
+
+/// `OutputUpdate` and `StateUpdate` returned by the `outputs()` and `state_update()`
+/// functions of the block, and the `BlockFields` implementation writing them
+pub fn generate_fields(block_repr: &rblock::Block) -> TokenStream2 {
+  let block_ident = syn::Ident::new(
+    &block_repr.name, block_repr.span.unwrap()
+  );
+  let lifetime = &block_repr.lifetime;
+  let ident = |field: &rblock::BlockField| syn::Ident::new(&field.name, field.span.unwrap());
+  let outputs = block_repr.outputs();
+  let states = block_repr.states();
+
+  let output_fields = outputs.iter().map(|x| {
+    let (name, tpe) = (ident(x), &x.value_type);
+    quote!(pub #name: #tpe)
+  });
+  let state_fields = states.iter().map(|x| {
+    let (name, tpe) = (ident(x), &x.value_type);
+    if matches!(x.btype, rblock::BlockFieldType::ContinuousState) {
+      let der = format_ident!("{}_dot", name);
+      quote!(pub #der: #tpe, pub #name: Option<#tpe>)
+    } else {
+      quote!(pub #name: Option<#tpe>)
+    }
+  });
+
+  let write_outputs = outputs.iter().map(|x| {
+    let name = ident(x);
+    quote!(dataflow_core::block::Access::set(&self.#name, update.#name);)
+  });
+  let write_states = states.iter().map(|x| {
+    let name = ident(x);
+    let set = quote!(if let Some(x) = update.#name { dataflow_core::block::Access::set(&self.#name, x) });
+    if matches!(x.btype, rblock::BlockFieldType::ContinuousState) {
+      let der = format_ident!("{}_dot", name);
+      quote!(dataflow_core::block::DerivativeAccess::der_set(&self.#name, update.#der); #set)
+    } else {
+      set
+    }
+  });
+
+  let input_ports = block_repr.inputs().into_iter().map(|x| {
+    let (name, name_str) = (ident(x), &x.name);
+    quote!(dataflow_core::block::InputPort::new(#name_str, self.#name.source()))
+  });
+  let output_ports = outputs.iter().map(|x| {
+    let (name, name_str) = (ident(x), &x.name);
+    quote!(dataflow_core::block::OutputPort::new(#name_str, (&self.#name).into()))
+  });
+
+  let named = block_repr.parameters().into_iter().chain(states.iter().copied()).chain(outputs.iter().copied());
+  let items = named.map(|x| {
+    let (name, name_str) = (ident(x), &x.name);
+    if x.shape.is_empty() {
+      quote!(dataflow_core::block::NamedItem::new(#name_str, &self.#name))
+    } else {
+      quote!(dataflow_core::block::NamedItem::array(#name_str, self.#name.items()))
+    }
+  });
+  let initial_values = block_repr.parameters().into_iter().map(|x| (x, quote!(Parameter)))
+    .chain(states.iter().map(|x| (*x, quote!(State))))
+    .filter(|(x, _)| x.init.is_some())
+    .map(|(x, kind)| {
+      let name = ident(x);
+      quote!(dataflow_core::block::InitialValue::#kind(&self.#name))
+    });
+  let invalid_states = block_repr.i_state.iter().filter(|x| x.field.shape.is_empty()).map(|x| {
+    let (name, name_str) = (ident(&x.field), &x.field.name);
+    quote!((!dataflow_core::block::ValidState::is_valid(&self.#name)).then_some(#name_str))
+  });
+
+  quote!{
+    pub struct OutputUpdate {
+      #(#output_fields),*
+    }
+
+    pub struct StateUpdate {
+      #(#state_fields),*
+    }
+
+    impl<#lifetime> dataflow_core::block::BlockFields for #block_ident<#lifetime> {
+      type OutputUpdate = OutputUpdate;
+      type StateUpdate = StateUpdate;
+
+      const FEEDTHROUGH: Option<&'static [dataflow_core::block::Feedthrough]> = FEEDTHROUGH;
+
+      fn write_outputs(&self, update: OutputUpdate) {
+        #(#write_outputs)*
+      }
+
+      fn write_states(&self, update: StateUpdate) {
+        #(#write_states)*
+      }
+
+      fn port_list(&self) -> (Vec<dataflow_core::block::InputPort>, Vec<dataflow_core::block::OutputPort>) {
+        (vec![#(#input_ports),*], vec![#(#output_ports),*])
+      }
+
+      fn field_items(&self) -> Vec<dataflow_core::block::NamedItem> {
+        vec![#(#items),*]
+      }
+
+      fn field_initial_values(&self) -> Vec<dataflow_core::block::InitialValue<'_>> {
+        vec![#(#initial_values),*]
+      }
+
+      fn field_invalid_states(&self) -> Vec<&'static str> {
+        let states: Vec<Option<&'static str>> = vec![#(#invalid_states),*];
+        states.into_iter().flatten().collect()
+      }
+    }
+  }
+}
+
+/// Implements `ApplyOutputUpdate`, `ApplyStateUpdate` and `IBlock` for the block of an
+/// `impl` with `fn outputs(&self) -> OutputUpdate` and optionally `fn state_update(&self) -> StateUpdate`.
+/// The computation is `Functional` without a state update, otherwise `Mixed`, or `State` if every
+/// output declares `#[feedthrough()]`, i.e. is computed from the state only. `zero_crossings` and `sample_time`
+/// are forwarded to `IBlock` when defined.
+pub fn generate_block_impl(item: &syn::ItemImpl) -> Res<TokenStream2> {
+  if let Some((_, path, _)) = &item.trait_ {
+    cerror!(path, "#[block_impl] applies to the inherent `impl` of a block")
+  }
+  let defines = |name: &str| item.items.iter().any(|x| matches!(x, syn::ImplItem::Method(m) if m.sig.ident == name));
+  if !defines("outputs") {
+    cerror!(item, "#[block_impl] requires `fn outputs(&self) -> OutputUpdate`")
+  }
+  let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+  let self_ty = &item.self_ty;
+  let fields = quote!(<#self_ty as dataflow_core::block::BlockFields>);
+
+  let (state_update, computation) = if defines("state_update") {
+    (
+      quote!{
+        impl #impl_generics dataflow_core::block::ApplyStateUpdate for #self_ty #where_clause {
+          fn apply(&self) {
+            #fields::write_states(self, self.state_update())
+          }
+        }
+      },
+      quote!{
+        let state_update_fn = dataflow_core::block::StateUpdateFunction { f: self };
+        let output_update_fn = dataflow_core::block::OutputUpdateFunction { f: self };
+        if #fields::state_only_outputs() {
+          dataflow_core::block::BlockComputation::State(dataflow_core::block::StateBlockComputation {
+            state_update_fn, output_update_fn
+          })
+        } else {
+          dataflow_core::block::BlockComputation::Mixed(dataflow_core::block::MixedBlockComputation {
+            state_update_fn, output_update_fn
+          })
+        }
+      }
+    )
+  } else {
+    (
+      quote!(),
+      quote!{
+        dataflow_core::block::BlockComputation::Functional(dataflow_core::block::FunctionalBlockComputation {
+          output_update_fn: dataflow_core::block::OutputUpdateFunction { f: self }
+        })
+      }
+    )
+  };
+  let zero_crossings = if defines("zero_crossings") {
+    quote!{
+      fn zero_crossings(&self) -> Vec<f64> {
+        <#self_ty>::zero_crossings(self)
+      }
+    }
+  } else {
+    quote!()
+  };
+  let sample_time = if defines("sample_time") {
+    quote!{
+      fn sample_time(&self) -> dataflow_core::block::SampleTime {
+        <#self_ty>::sample_time(self)
+      }
+    }
+  } else {
+    quote!()
+  };
+
+  Ok(quote!{
+    #item
+
+    impl #impl_generics dataflow_core::block::ApplyOutputUpdate for #self_ty #where_clause {
+      fn apply(&self) {
+        #fields::write_outputs(self, self.outputs())
+      }
+    }
+
+    #state_update
+
+    impl #impl_generics dataflow_core::block::IBlock for #self_ty #where_clause {
+      fn get_computation(&self) -> dataflow_core::block::BlockComputation<'_> {
+        #computation
+      }
+
+      fn input_ports(&self) -> Vec<dataflow_core::block::InputPort> {
+        #fields::port_list(self).0
+      }
+
+      fn output_ports(&self) -> Vec<dataflow_core::block::OutputPort> {
+        #fields::port_list(self).1
+      }
+
+      fn feedthrough(&self) -> Option<&'static [dataflow_core::block::Feedthrough]> {
+        #fields::FEEDTHROUGH
+      }
+
+      #zero_crossings
+
+      #sample_time
+
+      fn named_items(&self) -> Vec<dataflow_core::block::NamedItem> {
+        #fields::field_items(self)
+      }
+
+      fn initial_values(&self) -> Vec<dataflow_core::block::InitialValue<'_>> {
+        #fields::field_initial_values(self)
+      }
+
+      fn invalid_states(&self) -> Vec<&'static str> {
+        #fields::field_invalid_states(self)
+      }
+    }
+  })
+}
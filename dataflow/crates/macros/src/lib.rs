@@ -1,8 +1,8 @@
 use proc_macro::TokenStream;
-use syn::{parse_macro_input, DeriveInput};
+use syn::{parse_macro_input, DeriveInput, ItemImpl};
 use quote::{quote};
-use block::{create_block_repr, generate_size, generate_new};
-use system::{create_system_repr, generate_storage};
+use block::{create_block_repr, generate_size, generate_new, generate_feedthrough, generate_fields, generate_block_impl};
+use system::create_system_repr;
 
 mod types;
 mod utils;
@@ -10,46 +10,64 @@ mod parsers;
 mod block;
 mod system;
 
-#[proc_macro_derive(Block, attributes(default, initial))]
+#[proc_macro_derive(Block, attributes(default, initial, feedthrough))]
 pub fn derive_block(input: TokenStream) -> TokenStream {
   let ast = parse_macro_input!(input as DeriveInput);
   let block_repr = create_block_repr(&ast);
   let output = block_repr.and_then(|repr| {
     let size_section = generate_size(&repr);
     let new_section = generate_new(&repr);
+    let feedthrough_section = generate_feedthrough(&repr);
+    let fields_section = generate_fields(&repr);
     Ok(quote!{
-      // #new_section
+      #new_section
       #size_section
+      #feedthrough_section
+      #fields_section
     })  
   });
 
   match output {
-    Ok(res) => {
-      let x: TokenStream = res.into();
-      eprintln!("{}", x.to_string());
-      x
-    },
+    Ok(res) => res.into(),
     Err(e) => e.to_compile_error().into()
   }
 }
 
-#[proc_macro_derive(System)]
+/// Implements `IBlock` from the `outputs()` and `state_update()` functions of a block
+/// deriving `Block`, see `generate_block_impl`
+#[proc_macro_attribute]
+pub fn block_impl(attr: TokenStream, input: TokenStream) -> TokenStream {
+  if !attr.is_empty() {
+    let attr = proc_macro2::TokenStream::from(attr);
+    return syn::Error::new_spanned(attr, "#[block_impl] takes no arguments").to_compile_error().into()
+  }
+  let item = parse_macro_input!(input as ItemImpl);
+  match generate_block_impl(&item) {
+    Ok(res) => res.into(),
+    Err(e) => e.to_compile_error().into()
+  }
+}
+
+/// Implements `ISystem` for a struct of blocks and a `&dyn SystemStorage` field, with an associated
+/// `SIZE` and `new(storage)`, which calls `connect(&mut self)` of the system.
+/// Parameter values of the system are declared on the blocks with `#[parameters(name = value)]`.
+#[proc_macro_derive(System, attributes(parameters))]
 pub fn derive_system(input: TokenStream) -> TokenStream {
   let ast = parse_macro_input!(input as DeriveInput);
   let system_repr = create_system_repr(&ast);
   let output= system_repr.and_then(|repr| {
-    let storage_section = generate_storage(&repr);
+    let size_section = system::generate_size(&repr);
+    let new_section = system::generate_new(&repr);
+    let system_section = system::generate_system(&repr);
     Ok(quote! {
-      #storage_section
+      #size_section
+      #new_section
+      #system_section
     })
   });
 
   match output {
-    Ok(res) => {
-      let x: TokenStream = res.into();
-      eprintln!("{}", x.to_string());
-      x
-    },
+    Ok(res) => res.into(),
     Err(e) => e.to_compile_error().into()
   }
 }
\ No newline at end of file
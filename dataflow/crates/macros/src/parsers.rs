@@ -11,7 +11,7 @@ pub fn parse_simple_path<'a, T>(segments: T) -> Res<SimpleType>
       lerror!(segment, "expected qualified simple type, found {:#?}", segment)
     }
   }).collect::<Res<Vec<syn::Ident>>>()?;
-  Ok(SimpleType {qualified_path: path})
+  Ok(SimpleType {qualified_path: path, shape: vec![]})
 }
 
 pub fn parse_qualified_simple_type(tpe: &syn::Type) -> Res<SimpleType> {
@@ -19,6 +19,15 @@ pub fn parse_qualified_simple_type(tpe: &syn::Type) -> Res<SimpleType> {
     syn::Type::Path(syn::TypePath {
       path: syn::Path {ref segments, ..}, ..
     }) => parse_simple_path(segments.iter()),
+    syn::Type::Array(syn::TypeArray {ref elem, ref len, ..}) => {
+      let mut element = parse_qualified_simple_type(elem)?;
+      let len = match len {
+        syn::Expr::Lit(syn::ExprLit {lit: syn::Lit::Int(ref n), ..}) => n.base10_parse::<usize>()?,
+        _ => cerror!(len, "array length must be an integer literal"),
+      };
+      element.shape.insert(0, len);
+      Ok(element)
+    },
     _ => cerror!(tpe, "expected qualified simple type, found {:#?}", tpe)
   }
 }
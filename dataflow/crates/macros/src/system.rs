@@ -1,133 +1,173 @@
 use proc_macro2::TokenStream as TokenStream2;
 use syn::{DeriveInput};
 use syn::spanned::Spanned;
+use syn::parse::Parser;
 use quote::{quote};
 use dataflow_reflect::system as rsystem;
-// use dataflow_reflect::block::AttachTo;
-use std::marker::PhantomData;
-use std::str::FromStr;
-use crate::types::{Res, SimpleType};
-use crate::utils::{cerror, lerror};
-use crate::parsers;
-
+use crate::types::{Res};
+use crate::utils::{cerror};
 
 
+/** Helpers */
 pub fn create_system_repr(ast: &DeriveInput) -> Res<rsystem::System> {
-  Ok(rsystem::System {
+  let mut system_repr = rsystem::System::new(ast.ident.to_string());
+  system_repr.span = Some(ast.span());
+  system_repr.lifetime = match ast.generics.lifetimes().next() {
+    Some(def) => Some(def.lifetime.clone()),
+    None => cerror!(ast, "System definition must have a lifetime parameter for its storage, e.g. `System<'a>`")
+  };
+  let fields = match ast.data {
+    syn::Data::Struct(syn::DataStruct{
+      fields: syn::Fields::Named(syn::FieldsNamed {
+        ref named, ..
+      }), ..
+    }) => named,
+    _ => cerror!(ast, "System definition must be a `struct` with named fields")
+  };
 
-  })
+  for field in fields.iter() {
+    let field_ident = field.ident.as_ref().unwrap();
+    match &field.ty {
+      syn::Type::Reference(_) => {
+        if system_repr.storage.is_some() {
+          cerror!(field, "System definition must have a single storage field")
+        }
+        system_repr.storage = Some(field_ident.clone());
+      },
+      syn::Type::Path(syn::TypePath { qself: None, path }) if path.segments.len() > 1 => {
+        let mut module = path.clone();
+        module.segments.pop();
+        let module = module.segments.pairs().map(|pair| pair.into_value()).collect::<Vec<_>>();
+        let leading_colon = &path.leading_colon;
+        system_repr.blocks.push(rsystem::SystemBlock {
+          name: field_ident.to_string(),
+          span: Some(field.span()),
+          module: quote!(#leading_colon #(#module)::*),
+          parameters: parse_parameters(field)?,
+        });
+      },
+      tpe => cerror!(tpe, "expected a block type with its module, e.g. `thermal_mass::Block<'a>`, \
+        or the storage `&'a dyn SystemStorage`")
+    }
+  }
+  if system_repr.storage.is_none() {
+    cerror!(ast, "System definition must have a storage field, e.g. `storage: &'a dyn SystemStorage`")
+  }
+  if system_repr.blocks.is_empty() {
+    cerror!(ast, "System definition must have at least one block")
+  }
+  Ok(system_repr)
 }
 
-pub fn generate_storage(system_repr: &rsystem::System) -> TokenStream2 {
-  let ident = "Thermal";
+/// Parses `#[parameters(cp = 4000.0, area = 1.0)]`
+fn parse_parameters(field: &syn::Field) -> Res<Vec<(syn::Ident, TokenStream2)>> {
+  let mut parameters = vec![];
+  for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("parameters")) {
+    let parser = |input: syn::parse::ParseStream| {
+      let content;
+      syn::parenthesized!(content in input);
+      let items = content.parse_terminated::<_, syn::Token![,]>(|item: syn::parse::ParseStream| {
+        let name = item.parse::<syn::Ident>()?;
+        item.parse::<syn::Token![=]>()?;
+        let value = item.parse::<syn::Expr>()?;
+        Ok((name, quote!(#value)))
+      })?;
+      Ok(items.into_iter().collect::<Vec<_>>())
+    };
+    parameters.extend(parser.parse2(attr.tokens.clone())?);
+  }
+  Ok(parameters)
+}
 
-  
 
+/// Associated `SIZE` of the system, the storage size required by `new`
+pub fn generate_size(system_repr: &rsystem::System) -> TokenStream2 {
+  let system_ident = syn::Ident::new(&system_repr.name, system_repr.span.unwrap());
+  let lifetime = &system_repr.lifetime;
+  let sizes = system_repr.blocks.iter().map(|block| {
+    let module = &block.module;
+    quote!(#module::SIZE)
+  });
   quote!{
-    pub mod #ident {
-      use dataflow_core::system::{SystemSize, SystemStorage};
-      use super::*;
-      pub struct StorageFacade;
+    impl<#lifetime> #system_ident<#lifetime> {
+      pub const SIZE: dataflow_core::system::SystemSize = dataflow_core::system::SystemSize::new()
+        #(.add(#sizes))*;
+    }
+  }
+}
 
-      pub const fn create_storage() -> StorageFacade {
-        const SS: SystemSize = SystemSize::new()
-          $(.add($block_size))+;
-        
-        struct StorageImpl {
-          r_param: [f64; SS.r_param],
-          b_param: [bool; SS.b_param],
-          r_state: [f64; SS.r_state],
-          r_state_der: [f64; SS.r_state],
-          b_state: [bool; SS.b_state],
-          r_out: [f64; SS.r_out],
-          b_out: [bool; SS.b_out],
-        }
-        
-        static mut STORAGE: StorageImpl = StorageImpl {
-          r_param: [0.0; SS.r_param],
-          b_param: [false; SS.b_param],
-          r_state: [0.0; SS.r_state],
-          r_state_der: [0.0; SS.r_state],
-          b_state: [false; SS.b_state],
-          r_out: [0.0; SS.r_out],
-          b_out: [false; SS.b_out],
+pub fn generate_new(system_repr: &rsystem::System) -> TokenStream2 {
+  let system_ident = syn::Ident::new(&system_repr.name, system_repr.span.unwrap());
+  let lifetime = &system_repr.lifetime;
+  let storage = &system_repr.storage;
+  let blocks = system_repr.blocks.iter().map(|block| {
+    let (name, module) = (syn::Ident::new(&block.name, block.span.unwrap()), &block.module);
+    quote!(#name: #module::new(storage, &mut counters))
+  });
+
+  quote!{
+    impl<#lifetime> #system_ident<#lifetime> {
+      /// Creates the system on `storage`, which must not be shared with other systems. Fails if it is
+      /// smaller than `SIZE`. Connects the blocks with `connect(&mut self)` and sets the parameters of the system.
+      pub fn new(
+        storage: &#lifetime dyn dataflow_core::system::SystemStorage
+      ) -> dataflow_core::Result<#system_ident<#lifetime>> {
+        storage.check_size(Self::SIZE)?;
+        let mut counters = dataflow_core::system::SystemCounters::new();
+        let mut instance = #system_ident {
+          #storage: storage,
+          #(#blocks),*
         };
-        
-        
-        
-        impl SystemStorage for StorageFacade {
-          fn sizes(&self) -> SystemSize {
-            SS
-          }
-      
-          fn r_param_get(&self, ind: usize) -> &f64 {
-            unsafe { &STORAGE.r_param[ind] }
-          }
-          fn r_param_set(&self, ind: usize, value: f64) {
-            unsafe { STORAGE.r_param[ind] = value }
-          }
-      
-          fn b_param_get(&self, ind: usize) -> &bool {
-            unsafe {&STORAGE.b_param[ind]}
-          }
-          fn b_param_set(&self, ind: usize, value: bool) {
-            unsafe {STORAGE.b_param[ind] = value}
-      
-          }
-          
-          fn r_state_get(&self, ind: usize) -> &f64 {
-            unsafe {&STORAGE.r_state[ind]}
-      
-          }
-          fn r_state_set(&self, ind: usize, value: f64) {
-            unsafe {STORAGE.r_state[ind] = value}
-      
-          }
-          fn r_state_der_get(&self, ind: usize) -> &f64 {
-            unsafe {&STORAGE.r_state_der[ind]}
-      
-          }
-          fn r_state_der_set(&self, ind: usize, value: f64) {
-            unsafe {STORAGE.r_state_der[ind] = value}
-      
-          }
-          
-          fn b_state_get(&self, ind: usize) -> &bool {
-            unsafe {&STORAGE.b_state[ind]}
-      
-          }
-          fn b_state_set(&self, ind: usize, value: bool) {
-            unsafe {STORAGE.b_state[ind] = value}
-      
-          }
-      
-          fn r_out_get(&self, ind: usize) -> &f64 {
-            unsafe {&STORAGE.r_out[ind]}
-      
-          }
-          fn r_out_set(&self, ind: usize, value: f64) {
-            unsafe {STORAGE.r_out[ind] = value}
-      
-          }
-          
-          fn b_out_get(&self, ind: usize) -> &bool {
-            unsafe {&STORAGE.b_out[ind]}
-      
-          }
-          fn b_out_set(&self, ind: usize, value: bool) {
-            unsafe {STORAGE.b_out[ind] = value}
-      
-          }
-      
+        instance.connect();
+        dataflow_core::system::ISystem::init(&instance);
+        Ok(instance)
+      }
+    }
+  }
+}
+
+pub fn generate_system(system_repr: &rsystem::System) -> TokenStream2 {
+  let system_ident = syn::Ident::new(&system_repr.name, system_repr.span.unwrap());
+  let lifetime = &system_repr.lifetime;
+  let storage = &system_repr.storage;
+  let n_blocks = system_repr.blocks.len();
+  let idents: Vec<syn::Ident> = system_repr.blocks.iter()
+    .map(|block| syn::Ident::new(&block.name, block.span.unwrap()))
+    .collect();
+  let indices = 0..n_blocks;
+  let names = system_repr.blocks.iter().map(|block| &block.name);
+  let indices2 = indices.clone();
+  let parameters = system_repr.blocks.iter().zip(idents.iter()).flat_map(|(block, ident)| {
+    block.parameters.iter().map(move |(name, value)| {
+      quote!(dataflow_core::block::Access::set(&self.#ident.#name, #value);)
+    })
+  });
+
+  quote!{
+    impl<#lifetime> dataflow_core::system::ISystem<#lifetime> for #system_ident<#lifetime> {
+      const N_BLOCKS: usize = #n_blocks;
+
+      fn storage(&self) -> &dyn dataflow_core::system::SystemStorage {
+        self.#storage
+      }
+
+      fn block(&#lifetime self, i: usize) -> Option<&#lifetime dyn dataflow_core::block::IBlock> {
+        match i {
+          #(#indices => Some(&self.#idents),)*
+          _ => None
+        }
+      }
+
+      fn block_name(&self, i: usize) -> Option<&'static str> {
+        match i {
+          #(#indices2 => Some(#names),)*
+          _ => None
         }
-        
-        StorageFacade
       }
-      const FACADE: StorageFacade = create_storage();
 
-      pub const fn facade() -> StorageFacade {
-          FACADE
+      fn init(&self) {
+        #(#parameters)*
       }
-    } 
+    }
   }
-}
\ No newline at end of file
+}
@@ -4,7 +4,9 @@ pub type Res<T> = Result<T, syn::Error>;
 
 #[derive(Default, Debug)]
 pub struct SimpleType {
-  pub qualified_path: Vec<syn::Ident>
+  pub qualified_path: Vec<syn::Ident>,
+  /// Array lengths, outermost first, for array types like `[[f64; 3]; 2]`
+  pub shape: Vec<usize>,
 }
 
 #[derive(Default, Debug)]
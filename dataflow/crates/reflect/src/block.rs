@@ -1,12 +1,13 @@
 use std::str::FromStr;
 use std::marker::PhantomData;
-use proc_macro2::Span;
+use proc_macro2::{Span, TokenStream};
 
 #[derive(Default, Debug, Clone)]
 pub enum DType {
   #[default]
   Real,
-  Bool
+  Bool,
+  Int
 }
 
 #[derive(Default, Debug, Clone)]
@@ -39,14 +40,27 @@ pub struct BlockField {
   pub name: String,
   pub span: Option<Span>,
   pub dtype: DType,
-  pub btype: BlockFieldType
+  pub btype: BlockFieldType,
+  /// Array lengths, empty for scalars
+  pub shape: Vec<usize>,
+  /// Type of the values behind the handle, e.g. `f64` or `[f64; 3]`
+  pub value_type: Option<TokenStream>,
+  /// Expression passed to `Initial::init` in the generated `new`
+  pub init: Option<TokenStream>,
+}
+
+impl BlockField {
+  /// Number of storage slots taken by the field
+  pub fn slots(&self) -> usize {
+    self.shape.iter().product()
+  }
 }
 
 
 #[derive(Default, Debug)]
 pub struct Parameter<T> {
   pub field: BlockField,
-  pub default: Option<T>
+  pub _marker: PhantomData<T>,
 }
 
 #[derive(Default, Debug)]
@@ -58,37 +72,84 @@ pub struct Input<T> {
 #[derive(Default, Debug)]
 pub struct Output<T> {
   pub field: BlockField,
+  /// Names of the inputs the output is computed from directly, if declared
+  pub feedthrough: Option<Vec<syn::Ident>>,
   pub _marker: PhantomData<T>,
 }
 
 #[derive(Default, Debug)]
 pub struct State<T> {
   pub field: BlockField,
-  pub initial: T,
+  pub _marker: PhantomData<T>,
 }
 
 #[derive(Default, Debug)]
 pub struct Block {
   pub name: String,
   pub span: Option<Span>,
+  /// Lifetime of the storage borrowed by the block
+  pub lifetime: Option<syn::Lifetime>,
 
   pub r_param: Vec<Parameter<f64>>,
   pub b_param: Vec<Parameter<bool>>,
+  pub i_param: Vec<Parameter<i64>>,
 
   pub r_state: Vec<State<f64>>,
+  /// Discrete states of type f64, `r_state` holds the continuous ones
+  pub r_dstate: Vec<State<f64>>,
   pub b_state: Vec<State<bool>>,
+  pub i_state: Vec<State<i64>>,
 
   pub r_in: Vec<Input<f64>>,
   pub b_in: Vec<Input<bool>>,
+  pub i_in: Vec<Input<i64>>,
 
   pub r_out: Vec<Output<f64>>,
   pub b_out: Vec<Output<bool>>,
+  pub i_out: Vec<Output<i64>>,
 }
 
 impl Block {
   pub fn new(name: String) -> Block {
     Block {name, ..Block::default()}
   }
+
+  pub fn input_names(&self) -> Vec<&String> {
+    self.r_in.iter().map(|x| &x.field.name)
+      .chain(self.b_in.iter().map(|x| &x.field.name))
+      .chain(self.i_in.iter().map(|x| &x.field.name))
+      .collect()
+  }
+
+  pub fn inputs(&self) -> Vec<&BlockField> {
+    self.r_in.iter().map(|x| &x.field)
+      .chain(self.b_in.iter().map(|x| &x.field))
+      .chain(self.i_in.iter().map(|x| &x.field))
+      .collect()
+  }
+
+  pub fn outputs(&self) -> Vec<&BlockField> {
+    self.r_out.iter().map(|x| &x.field)
+      .chain(self.b_out.iter().map(|x| &x.field))
+      .chain(self.i_out.iter().map(|x| &x.field))
+      .collect()
+  }
+
+  pub fn parameters(&self) -> Vec<&BlockField> {
+    self.r_param.iter().map(|x| &x.field)
+      .chain(self.b_param.iter().map(|x| &x.field))
+      .chain(self.i_param.iter().map(|x| &x.field))
+      .collect()
+  }
+
+  /// Continuous and discrete states
+  pub fn states(&self) -> Vec<&BlockField> {
+    self.r_state.iter().map(|x| &x.field)
+      .chain(self.r_dstate.iter().map(|x| &x.field))
+      .chain(self.b_state.iter().map(|x| &x.field))
+      .chain(self.i_state.iter().map(|x| &x.field))
+      .collect()
+  }
 }
 
 pub trait AttachTo {
@@ -108,9 +169,13 @@ macro_rules! attach_impl {
 
 attach_impl!(Parameter<f64>, r_param);
 attach_impl!(Parameter<bool>, b_param);
+attach_impl!(Parameter<i64>, i_param);
 attach_impl!(Input<f64>, r_in);
 attach_impl!(Input<bool>, b_in);
+attach_impl!(Input<i64>, i_in);
 attach_impl!(Output<f64>, r_out);
 attach_impl!(Output<bool>, b_out);
+attach_impl!(Output<i64>, i_out);
 attach_impl!(State<f64>, r_state);
 attach_impl!(State<bool>, b_state);
+attach_impl!(State<i64>, i_state);
@@ -1,4 +1,29 @@
+use proc_macro2::{Span, TokenStream};
+
+#[derive(Debug)]
+pub struct SystemBlock {
+  pub name: String,
+  pub span: Option<Span>,
+  /// Module of the block type, holding its `SIZE` and `new`
+  pub module: TokenStream,
+  /// Parameter values of the system overriding the defaults of the block
+  pub parameters: Vec<(syn::Ident, TokenStream)>,
+}
+
 #[derive(Default, Debug)]
 pub struct System {
+  pub name: String,
+  pub span: Option<Span>,
+  /// Lifetime of the storage borrowed by the system
+  pub lifetime: Option<syn::Lifetime>,
+  /// Field holding the `&dyn SystemStorage`
+  pub storage: Option<syn::Ident>,
+  /// Blocks in the order of their fields
+  pub blocks: Vec<SystemBlock>,
+}
 
-}
\ No newline at end of file
+impl System {
+  pub fn new(name: String) -> System {
+    System {name, ..System::default()}
+  }
+}
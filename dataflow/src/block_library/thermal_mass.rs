@@ -1,77 +1,33 @@
 use dataflow_core::block::*;
-use dataflow_core::system::{SystemStorage, SystemCounters};
+use dataflow_macros as mac;
 
+#[derive(mac::Block)]
 pub struct Block<'a> {
+  #[default = 1.0]
   pub cp: Parameter<'a, f64>,
+  #[default = 10.0]
   pub h: Parameter<'a, f64>,
+  #[default = 1.0]
   pub area: Parameter<'a, f64>,
+  #[default = 20.0]
   pub t_amb: Parameter<'a, f64>,
   pub qdot: Input<'a, f64>,
+  #[initial = 20.0]
   pub t: ContinuousState<'a, f64>,
+  #[feedthrough()]
   pub t_out: Output<'a, f64>,
 }
 
+#[mac::block_impl]
 impl<'a> Block<'a> {
   pub fn outputs(&self) -> OutputUpdate {
-    OutputUpdate { t_out: *self.t}
+    OutputUpdate { t_out: self.t.get()}
   }
 
   pub fn state_update(&self) -> StateUpdate {
     StateUpdate { 
-      t_dot: (*self.qdot + *self.h * *self.area * (*self.t_amb - *self.t)) / *self.cp,
+      t_dot: (self.qdot.get() + self.h.get() * self.area.get() * (self.t_amb.get() - self.t.get())) / self.cp.get(),
       t: None
     }
   }
 }
-
-/* BEGIN autogenerated code */
-pub const SIZE: BlockSize = BlockSize {
-  r_param: 4,
-  r_in: 1,
-  r_state: 1,
-  r_out: 1,
-  ..BlockSize::new()
-};
-pub struct OutputUpdate {
-  pub t_out: f64,
-}
-
-pub struct StateUpdate {
-  pub t_dot: f64,
-  pub t: Option<f64>,
-}
-
-pub fn new<'a>(storage: &'a dyn SystemStorage, counters: &mut SystemCounters) -> Block<'a> {
-  Block {
-    cp: Parameter::<f64>::new(storage, counters.next_r_param()).init(1.0),
-    h: Parameter::<f64>::new(storage, counters.next_r_param()).init(10.0),
-    area: Parameter::<f64>::new(storage, counters.next_r_param()).init(1.0),
-    t_amb: Parameter::<f64>::new(storage, counters.next_r_param()).init(20.0),
-    t: ContinuousState::new(storage, counters.next_r_state()).init(20.0),
-    qdot: Input::new(storage),
-    t_out: Output::new(storage, counters.next_r_out()),
-  }
-}
-
-impl<'a> ApplyOutputUpdate for Block<'a> {
-  fn apply(&self) {
-    let output = self.outputs();
-    self.t_out.set(output.t_out);
-  }
-}
-
-impl<'a> ApplyStateUpdate for Block<'a> {
-  fn apply(&self) {
-    let state_update = self.state_update();
-    self.t.der_set(state_update.t_dot);
-  }
-}
-
-impl<'a> IBlock for Block<'a> {
-  fn get_computation(&self) -> BlockComputation {
-    BlockComputation::State(StateBlockComputation {
-      state_update_fn: StateUpdateFunction {f: self}, 
-      output_update_fn: OutputUpdateFunction {f: self}
-    })
-  }
-}
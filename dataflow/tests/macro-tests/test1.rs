@@ -1,5 +1,3 @@
-use dataflow_core::block::{Parameter, Input, ContinuousState, Output};
-use dataflow_macros as mac;
 use dataflow::block_library::{converter_b2f, thermal_mass, hysteresis};
 
 // #[derive(Block)]
@@ -14,42 +12,459 @@ use dataflow::block_library::{converter_b2f, thermal_mass, hysteresis};
 //   t: &'a T
 // }
 
-// #[allow(dead_code)]
-// #[derive(mac::Block)]
-// struct ThermalMass<'a> {
-//   #[default = 100.0]
-//   pub cp: Parameter<'a, f64>,
-//   #[default = 10.0]
-//   pub h: Parameter<'a, f64>,
-//   #[default = 1.0]
-//   pub area: Parameter<'a, f64>,
-//   #[default = 1.0]
-//   pub t_amb: Parameter<'a, f64>,  
-//   pub qdot: Input<'a, f64>,
-//   #[initial = 20.0]
-//   pub t: ContinuousState<'a, f64>,
-//   pub t_out: Output<'a, f64>,
-// }
+mod thermal_mass_def {
+  use dataflow_core::block::{Parameter, Input, ContinuousState, Output};
+  use dataflow_macros as mac;
 
-// use dataflow_core::system::{SystemSize, SystemStorage, ISystem, SystemCounters, static_storage};
-// use dataflow_core::block::{IBlock, Access, DerivativeAccess, BlockComputation, UpdateComputation};
-// use crate::block_library::{hysteresis, thermal_mass, converter_b2f};
-
-// static_storage!(STORAGE, 
-//   thermal_mass::Block<'a>, 
-//   hysteresis::Block<'a>, 
-//   converter_b2f::Block<'a>
-// );
-// #[allow(dead_code)]
-// #[derive(mac::System)]
-// struct TemperatureController<'a> {
-//   mass: thermal_mass::Block<'a>,
-//   hyst_component: hysteresis::Block<'a>,
-//   b2f: converter_b2f::Block<'a>
-// }
+  #[allow(dead_code)]
+  #[derive(mac::Block)]
+  pub struct ThermalMass<'a> {
+    #[default = 100.0]
+    pub cp: Parameter<'a, f64>,
+    #[default = 10.0]
+    pub h: Parameter<'a, f64>,
+    #[default = 1.0]
+    pub area: Parameter<'a, f64>,
+    #[default = 1.0]
+    pub t_amb: Parameter<'a, f64>,  
+    pub qdot: Input<'a, f64>,
+    #[initial = 20.0]
+    pub t: ContinuousState<'a, f64>,
+    pub t_out: Output<'a, f64>,
+  }
+}
+
+mod controller_def {
+  use dataflow_core::system::SystemStorage;
+  use dataflow_macros as mac;
+  use dataflow::block_library::{converter_b2f, thermal_mass, hysteresis};
+
+  #[derive(mac::System)]
+  pub struct TemperatureController<'a> {
+    pub storage: &'a dyn SystemStorage,
+    #[parameters(cp = 4000.0, t_amb = 15.0)]
+    pub mass: thermal_mass::Block<'a>,
+    #[parameters(out_inverted = true)]
+    pub hyst_component: hysteresis::Block<'a>,
+    pub b2f: converter_b2f::Block<'a>
+  }
+
+  impl<'a> TemperatureController<'a> {
+    pub fn connect(&mut self) {
+      self.hyst_component.in1.connect(&self.mass.t_out);
+      self.b2f.in1.connect(&self.hyst_component.out1);
+      self.mass.qdot.connect(&self.b2f.out1);
+    }
+  }
+
+  /// Second system in the same module
+  #[derive(mac::System)]
+  pub struct Heater<'a> {
+    pub storage: &'a dyn SystemStorage,
+    #[parameters(true_value = 500.0)]
+    pub b2f: converter_b2f::Block<'a>,
+    pub mass: thermal_mass::Block<'a>,
+  }
+
+  impl<'a> Heater<'a> {
+    pub fn connect(&mut self) {
+      self.mass.qdot.connect(&self.b2f.out1);
+    }
+  }
+}
+
+mod hysteresis_def {
+  use dataflow_core::block::{Parameter, Input, Output, DiscreteState, Access, ReadAccess};
+  use dataflow_macros as mac;
+
+  #[allow(dead_code)]
+  #[derive(mac::Block)]
+  pub struct Hysteresis<'a> {
+    pub low_threshold: Parameter<'a, f64>,
+    pub high_threshold: Parameter<'a, f64>,
+    pub in1: Input<'a, f64>,
+    #[feedthrough()]
+    pub out1: Output<'a, bool>,
+    pub in_enable: Input<'a, bool>,
+    #[feedthrough(in_enable)]
+    pub enabled: Output<'a, bool>,
+    pub state_high: DiscreteState<'a, bool>
+  }
+
+  #[mac::block_impl]
+  impl<'a> Hysteresis<'a> {
+    pub fn outputs(&self) -> OutputUpdate {
+      OutputUpdate { out1: self.state_high.get(), enabled: self.in_enable.get() }
+    }
+
+    pub fn state_update(&self) -> StateUpdate {
+      StateUpdate { state_high: Some(self.in1.get() > self.high_threshold.get()) }
+    }
+  }
+}
+
+mod mode_selector_def {
+  use dataflow_core::block::{Parameter, Input, Output, DiscreteState};
+  use dataflow_macros as mac;
+
+  #[allow(dead_code)]
+  #[derive(mac::Block)]
+  pub struct ModeSelector<'a> {
+    pub n_modes: Parameter<'a, i64>,
+    pub request: Input<'a, i64>,
+    pub enable: Input<'a, bool>,
+    pub mode: Output<'a, i64>,
+    pub switches: DiscreteState<'a, i64>,
+  }
+}
+
+mod unit_delay_def {
+  use dataflow_core::block::{Parameter, Input, Output, DiscreteState};
+  use dataflow_macros as mac;
+
+  #[allow(dead_code)]
+  #[derive(mac::Block)]
+  pub struct UnitDelay<'a> {
+    pub initial: Parameter<'a, f64>,
+    pub in1: Input<'a, f64>,
+    #[feedthrough()]
+    pub out1: Output<'a, f64>,
+    pub previous: DiscreteState<'a, f64>,
+  }
+}
+
+mod point_mass_def {
+  use dataflow_core::block::{Parameter, Input, Output, ContinuousState, Access, ReadAccess};
+  use dataflow_macros as mac;
+
+  #[allow(dead_code)]
+  #[derive(mac::Block)]
+  pub struct PointMass<'a> {
+    #[default = 1.0]
+    pub mass: Parameter<'a, f64>,
+    #[default([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])]
+    pub damping: Parameter<'a, [[f64; 3]; 3]>,
+    pub force: Input<'a, [f64; 3]>,
+    pub v: ContinuousState<'a, [f64; 3]>,
+    #[feedthrough()]
+    pub v_out: Output<'a, [f64; 3]>,
+  }
+
+  #[mac::block_impl]
+  impl<'a> PointMass<'a> {
+    pub fn outputs(&self) -> OutputUpdate {
+      OutputUpdate { v_out: self.v.get() }
+    }
+
+    pub fn state_update(&self) -> StateUpdate {
+      let (force, v, damping) = (self.force.get(), self.v.get(), self.damping.get());
+      let v_dot = std::array::from_fn(|i| {
+        (force[i] - (0..3).map(|j| damping[i][j] * v[j]).sum::<f64>()) / self.mass.get()
+      });
+      StateUpdate { v_dot, v: None }
+    }
+  }
+}
+
+mod leaky_def {
+  use dataflow_core::block::{Input, Output, ContinuousState, Access, ReadAccess};
+  use dataflow_macros as mac;
+
+  #[derive(mac::Block)]
+  pub struct Leaky<'a> {
+    pub in1: Input<'a, f64>,
+    pub x: ContinuousState<'a, f64>,
+    pub out1: Output<'a, f64>,
+  }
+
+  #[mac::block_impl]
+  impl<'a> Leaky<'a> {
+    /// Reads the input directly, without declaring it
+    pub fn outputs(&self) -> OutputUpdate {
+      OutputUpdate { out1: self.x.get() + self.in1.get() }
+    }
+
+    pub fn state_update(&self) -> StateUpdate {
+      StateUpdate { x_dot: -self.x.get(), x: None }
+    }
+  }
+}
+
+mod leaky_loop_def {
+  use dataflow_core::system::SystemStorage;
+  use dataflow_macros as mac;
+  use super::leaky_def;
+
+  #[derive(mac::System)]
+  pub struct LeakyLoop<'a> {
+    pub storage: &'a dyn SystemStorage,
+    pub first: leaky_def::Leaky<'a>,
+    pub second: leaky_def::Leaky<'a>,
+  }
+
+  impl<'a> LeakyLoop<'a> {
+    pub fn connect(&mut self) {
+      self.second.in1.connect(&self.first.out1);
+      self.first.in1.connect(&self.second.out1);
+    }
+  }
+}
+
+mod supervisor_def {
+  use dataflow_core::block::{Input, DiscreteState, StateEnum, ReadAccess};
+  use dataflow_macros as mac;
+
+  #[derive(Clone, Copy)]
+  pub enum Mode { Off, On }
+
+  impl StateEnum for Mode {
+    fn to_i64(self) -> i64 {
+      self as i64
+    }
+    fn from_i64(v: i64) -> Option<Self> {
+      match v {
+        0 => Some(Mode::Off),
+        1 => Some(Mode::On),
+        _ => None
+      }
+    }
+    fn variant_name(v: i64) -> Option<&'static str> {
+      ["Off", "On"].get(usize::try_from(v).ok()?).copied()
+    }
+  }
+
+  #[allow(dead_code)]
+  #[derive(mac::Block)]
+  pub struct Supervisor<'a> {
+    pub request: Input<'a, bool>,
+    #[initial(Mode::On)]
+    pub mode: DiscreteState<'a, Mode>,
+  }
+
+  #[mac::block_impl]
+  impl<'a> Supervisor<'a> {
+    pub fn outputs(&self) -> OutputUpdate {
+      OutputUpdate {}
+    }
+
+    pub fn state_update(&self) -> StateUpdate {
+      let mode = if self.request.get() { Mode::On } else { Mode::Off };
+      StateUpdate { mode: Some(mode) }
+    }
+  }
+}
+
+mod supervised_def {
+  use dataflow_core::system::SystemStorage;
+  use dataflow_macros as mac;
+  use super::supervisor_def;
 
+  #[derive(mac::System)]
+  pub struct Supervised<'a> {
+    pub storage: &'a dyn SystemStorage,
+    pub supervisor: supervisor_def::Supervisor<'a>,
+  }
+
+  impl<'a> Supervised<'a> {
+    pub fn connect(&mut self) {}
+  }
+}
 
 #[test]
 fn tests() {
-  //let mass = ThermalMass::new();
-}
\ No newline at end of file
+  use dataflow_core::block::Access;
+  use dataflow_core::system::{DynamicStorage, SystemSize, SystemCounters};
+  let storage = DynamicStorage::new(SystemSize::new().add(thermal_mass_def::SIZE));
+  let mass = thermal_mass_def::new(&storage, &mut SystemCounters::new());
+  assert_eq!((mass.cp.get(), mass.h.get(), mass.area.get(), mass.t_amb.get()), (100.0, 10.0, 1.0, 1.0));
+  assert_eq!(mass.t.get(), 20.0);
+}
+
+#[test]
+fn generated_new_allocates_slots() {
+  use dataflow_core::block::Access;
+  use dataflow_core::system::{DynamicStorage, SystemSize, SystemCounters, SystemStorageItemId};
+  let storage = DynamicStorage::new(SystemSize::new().add(point_mass_def::SIZE).add(point_mass_def::SIZE));
+  let mut counters = SystemCounters::new();
+  let first = point_mass_def::new(&storage, &mut counters);
+  let second = point_mass_def::new(&storage, &mut counters);
+  assert_eq!(second.v.items()[0], SystemStorageItemId::RealState(3));
+  assert_eq!(SystemStorageItemId::from(&second.mass), SystemStorageItemId::RealParameter(10));
+  assert_eq!(SystemStorageItemId::from(&second.v_out), SystemStorageItemId::RealOutput(3));
+  assert_eq!((first.mass.get(), second.damping.get()[1]), (1.0, [0.0, 1.0, 0.0]));
+  assert!(first.force.source().is_none());
+}
+
+#[test]
+fn generated_new_initial_enum_state() {
+  use dataflow_core::block::Access;
+  use dataflow_core::system::{DynamicStorage, SystemSize, SystemCounters};
+  let storage = DynamicStorage::new(SystemSize::new().add(supervisor_def::SIZE));
+  let supervisor = supervisor_def::new(&storage, &mut SystemCounters::new());
+  assert!(matches!(supervisor.mode.get(), supervisor_def::Mode::On));
+}
+
+#[test]
+fn restore_rejects_invalid_enum_state() {
+  use dataflow_core::Error;
+  use dataflow_core::block::{Access, IBlock};
+  use dataflow_core::system::{ISystem, SystemStorage, DynamicStorage};
+  use supervised_def::Supervised;
+  let storage = DynamicStorage::new(Supervised::SIZE);
+  let system = Supervised::new(&storage).unwrap();
+  let checkpoint = storage.snapshot(0.0);
+  storage.i_state_set(0, 7);
+  assert_eq!(system.supervisor.invalid_states(), ["mode"]);
+  let corrupted = storage.snapshot(1.0);
+
+  system.restore(&checkpoint).unwrap();
+  assert!(matches!(system.supervisor.mode.get(), supervisor_def::Mode::On));
+  match system.restore(&corrupted) {
+    Err(Error::InvalidStates(paths)) => assert_eq!(paths, ["supervisor.mode"]),
+    _ => panic!("expected invalid states"),
+  }
+  assert!(matches!(system.supervisor.mode.get(), supervisor_def::Mode::On));
+}
+
+#[test]
+fn feedthrough_declaration() {
+  use dataflow_core::block::Feedthrough;
+  assert_eq!(hysteresis_def::FEEDTHROUGH, Some(&[
+    Feedthrough { output: "out1", inputs: &[] },
+    Feedthrough { output: "enabled", inputs: &["in_enable"] },
+  ][..]));
+}
+#[test]
+fn integer_fields_size() {
+  use dataflow_core::block::BlockSize;
+  assert!(mode_selector_def::SIZE == BlockSize {
+    i_param: 1,
+    i_state: 1,
+    b_in: 1,
+    i_in: 1,
+    i_out: 1,
+    ..BlockSize::new()
+  });
+}
+
+#[test]
+fn real_discrete_state_size() {
+  use dataflow_core::block::BlockSize;
+  assert!(unit_delay_def::SIZE == BlockSize {
+    r_param: 1,
+    r_dstate: 1,
+    r_in: 1,
+    r_out: 1,
+    ..BlockSize::new()
+  });
+}
+
+#[test]
+fn array_fields_size() {
+  use dataflow_core::block::BlockSize;
+  assert!(point_mass_def::SIZE == BlockSize {
+    r_param: 10,
+    r_state: 3,
+    r_in: 3,
+    r_out: 3,
+    ..BlockSize::new()
+  });
+}
+
+#[test]
+fn enum_state_size() {
+  use dataflow_core::block::BlockSize;
+  assert!(supervisor_def::SIZE == BlockSize { b_in: 1, i_state: 1, ..BlockSize::new() });
+}
+
+#[test]
+fn block_impl_state_computation() {
+  use dataflow_core::block::{IBlock, BlockComputation, DerivativeAccess, Access};
+  use dataflow_core::system::{DynamicStorage, SystemSize, SystemCounters};
+  let storage = DynamicStorage::new(SystemSize::new().add(point_mass_def::SIZE));
+  let mut mass = point_mass_def::new(&storage, &mut SystemCounters::new());
+  // Fed back from the output, which is still zero for the state update
+  mass.force.connect(&mass.v_out);
+  mass.v.set([1.0, 2.0, 0.0]);
+  let (state_update_fn, output_update_fn) = match mass.get_computation() {
+    BlockComputation::State(c) => (c.state_update_fn, c.output_update_fn),
+    _ => panic!("expected a state computation"),
+  };
+  state_update_fn.f.apply();
+  output_update_fn.f.apply();
+  assert_eq!(mass.v.der_get(), [-1.0, -2.0, 0.0]);
+  assert_eq!(mass.v_out.get(), [1.0, 2.0, 0.0]);
+  assert_eq!(mass.v.get(), [1.0, 2.0, 0.0]);
+
+  let names: Vec<(&str, usize)> = mass.named_items().iter().map(|x| (x.name, x.items.len())).collect();
+  assert_eq!(names, [("mass", 1), ("damping", 9), ("v", 3), ("v_out", 3)]);
+  assert_eq!(mass.initial_values().len(), 3);
+  assert_eq!((mass.input_ports()[0].name, mass.output_ports()[0].name), ("force", "v_out"));
+}
+
+#[test]
+fn block_impl_mixed_computation() {
+  use dataflow_core::block::{IBlock, BlockComputation};
+  use dataflow_core::system::{DynamicStorage, SystemSize, SystemCounters};
+  let storage = DynamicStorage::new(SystemSize::new().add(hysteresis_def::SIZE));
+  let hyst = hysteresis_def::new(&storage, &mut SystemCounters::new());
+  assert!(matches!(hyst.get_computation(), BlockComputation::Mixed(_)));
+  assert_eq!(hyst.feedthrough(), hysteresis_def::FEEDTHROUGH);
+}
+
+#[test]
+fn block_impl_functional_computation() {
+  use dataflow_core::block::{IBlock, BlockComputation};
+  use dataflow_core::system::{DynamicStorage, SystemSize, SystemCounters};
+  let storage = DynamicStorage::new(SystemSize::new().add(converter_b2f::SIZE));
+  let b2f = converter_b2f::new(&storage, &mut SystemCounters::new());
+  assert!(matches!(b2f.get_computation(), BlockComputation::Functional(_)));
+  assert_eq!(b2f.initial_values().len(), 2);
+}
+
+#[test]
+fn derived_system() {
+  use dataflow_core::block::Access;
+  use dataflow_core::system::{ISystem, SystemSize, SystemStorage, DynamicStorage};
+  use controller_def::TemperatureController;
+  assert_eq!(TemperatureController::SIZE, SystemSize::new()
+    .add(thermal_mass::SIZE)
+    .add(hysteresis::SIZE)
+    .add(converter_b2f::SIZE));
+
+  let storage = DynamicStorage::new(TemperatureController::SIZE);
+  let system = TemperatureController::new(&storage).unwrap();
+  assert_eq!(storage.sizes(), TemperatureController::SIZE);
+  assert_eq!(TemperatureController::N_BLOCKS, 3);
+  assert_eq!((system.block_name(1), system.block_name(3)), (Some("hyst_component"), None));
+  assert_eq!((system.mass.cp.get(), system.mass.t_amb.get(), system.mass.h.get()), (4000.0, 15.0, 10.0));
+  assert!(system.hyst_component.out_inverted.get());
+  assert_eq!(system.validate(), Ok(vec![]));
+  assert_eq!(system.computations().unwrap().len(), 5);
+}
+
+#[test]
+fn derived_systems_in_one_module() {
+  use dataflow_core::block::Access;
+  use dataflow_core::system::{SystemSize, DynamicStorage};
+  use controller_def::{Heater, TemperatureController};
+  assert_eq!(Heater::SIZE, SystemSize::new().add(converter_b2f::SIZE).add(thermal_mass::SIZE));
+
+  let (storage1, storage2) = (DynamicStorage::new(Heater::SIZE), DynamicStorage::new(TemperatureController::SIZE));
+  let heater = Heater::new(&storage1).unwrap();
+  let controller = TemperatureController::new(&storage2).unwrap();
+  assert_eq!(heater.b2f.true_value.get(), 500.0);
+  assert_eq!(controller.mass.cp.get(), 4000.0);
+  assert!(Heater::new(&DynamicStorage::new(SystemSize::new())).is_err());
+}
+
+#[test]
+fn block_impl_undeclared_feedthrough_loop() {
+  use dataflow_core::block::{IBlock, BlockComputation};
+  use dataflow_core::system::{DynamicStorage, ISystem};
+  let storage = DynamicStorage::new(leaky_loop_def::LeakyLoop::SIZE);
+  let system = leaky_loop_def::LeakyLoop::new(&storage).unwrap();
+  assert!(matches!(system.first.get_computation(), BlockComputation::Mixed(_)));
+  assert_eq!(system.algebraic_loops().len(), 1);
+  assert!(system.computations().is_err());
+}
@@ -0,0 +1,355 @@
+use dataflow_core::system::{SystemStorage, ISystem, SystemCounters, SystemSize, DynamicStorage};
+use dataflow_core::block::*;
+use dataflow_core::schedule::ConnectionIssue;
+use dataflow_core::simulator::Simulator;
+use dataflow_core::solver::Euler;
+use dataflow_core::Error;
+use dataflow::block_library::{hysteresis, converter_b2f};
+use dataflow::examples::temperature_controller;
+
+/// Feedthrough counterpart of the hysteresis block
+mod comparator {
+  use dataflow_core::block::*;
+  use dataflow_core::system::{SystemStorage, SystemCounters};
+
+  pub struct Block<'a> {
+    pub in1: Input<'a, f64>,
+    pub out1: Output<'a, bool>,
+  }
+
+  pub const SIZE: BlockSize = BlockSize {
+    r_in: 1,
+    b_out: 1,
+    ..BlockSize::new()
+  };
+
+  pub fn new<'a>(storage: &'a dyn SystemStorage, counters: &mut SystemCounters) -> Block<'a> {
+    Block {
+      in1: Input::new(storage),
+      out1: Output::new(storage, counters.next_b_out()),
+    }
+  }
+
+  impl<'a> ApplyOutputUpdate for Block<'a> {
+    fn apply(&self) {
+      self.out1.set(self.in1.get() > 0.0);
+    }
+  }
+
+  impl<'a> IBlock for Block<'a> {
+    fn get_computation(&self) -> BlockComputation<'_> {
+      BlockComputation::Functional(FunctionalBlockComputation {
+        output_update_fn: OutputUpdateFunction { f: self }
+      })
+    }
+
+    fn input_ports(&self) -> Vec<InputPort> {
+      vec![InputPort::new("in1", self.in1.source())]
+    }
+
+    fn output_ports(&self) -> Vec<OutputPort> {
+      vec![OutputPort::new("out1", (&self.out1).into())]
+    }
+  }
+}
+
+/// Counts its executions, optionally reading another counter
+mod counter {
+  use std::cell::Cell;
+  use dataflow_core::block::*;
+  use dataflow_core::system::{SystemStorage, SystemCounters};
+
+  pub struct Block<'a> {
+    pub in1: Input<'a, f64>,
+    pub out1: Output<'a, f64>,
+    pub sample_time: SampleTime,
+    pub calls: Cell<usize>,
+  }
+
+  pub const SIZE: BlockSize = BlockSize {
+    r_in: 1,
+    r_out: 1,
+    ..BlockSize::new()
+  };
+
+  pub fn new<'a>(storage: &'a dyn SystemStorage, counters: &mut SystemCounters, sample_time: SampleTime) -> Block<'a> {
+    Block {
+      in1: Input::new(storage),
+      out1: Output::new(storage, counters.next_r_out()),
+      sample_time,
+      calls: Cell::new(0),
+    }
+  }
+
+  impl<'a> ApplyOutputUpdate for Block<'a> {
+    fn apply(&self) {
+      self.calls.set(self.calls.get() + 1);
+      self.out1.set(self.calls.get() as f64);
+    }
+  }
+
+  impl<'a> IBlock for Block<'a> {
+    fn get_computation(&self) -> BlockComputation<'_> {
+      BlockComputation::Functional(FunctionalBlockComputation {
+        output_update_fn: OutputUpdateFunction { f: self }
+      })
+    }
+
+    fn input_ports(&self) -> Vec<InputPort> {
+      vec![InputPort::new("in1", self.in1.source())]
+    }
+
+    fn output_ports(&self) -> Vec<OutputPort> {
+      vec![OutputPort::new("out1", (&self.out1).into())]
+    }
+
+    fn feedthrough(&self) -> Option<&'static [Feedthrough]> {
+      Some(&[Feedthrough { output: "out1", inputs: &[] }])
+    }
+
+    fn sample_time(&self) -> SampleTime {
+      self.sample_time
+    }
+  }
+}
+
+/// `b2f` fed back into the hysteresis, or into the comparator when `through_comparator` is set
+struct LoopSystem<'a> {
+  storage: &'a DynamicStorage,
+  hyst: hysteresis::Block<'a>,
+  comparator: comparator::Block<'a>,
+  b2f: converter_b2f::Block<'a>,
+}
+
+impl<'a> LoopSystem<'a> {
+  const SIZE: SystemSize = SystemSize::new().add(hysteresis::SIZE).add(comparator::SIZE).add(converter_b2f::SIZE);
+
+  fn new(storage: &'a DynamicStorage, through_comparator: bool) -> LoopSystem<'a> {
+    let mut counters = SystemCounters::new();
+    let mut system = LoopSystem {
+      storage,
+      hyst: hysteresis::new(storage, &mut counters),
+      comparator: comparator::new(storage, &mut counters),
+      b2f: converter_b2f::new(storage, &mut counters),
+    };
+    system.hyst.in1.connect(&system.b2f.out1);
+    system.comparator.in1.connect(&system.b2f.out1);
+    if through_comparator {
+      system.b2f.in1.connect(&system.comparator.out1);
+    } else {
+      system.b2f.in1.connect(&system.hyst.out1);
+    }
+    system
+  }
+}
+
+impl<'a> ISystem<'a> for LoopSystem<'a> {
+  const N_BLOCKS: usize = 3;
+
+  fn storage(&self) -> &dyn SystemStorage {
+    self.storage
+  }
+
+  fn block(&'a self, i: usize) -> Option<&'a dyn IBlock> {
+    match i {
+      0 => Some(&self.hyst),
+      1 => Some(&self.comparator),
+      2 => Some(&self.b2f),
+      _ => None
+    }
+  }
+
+  fn block_name(&self, i: usize) -> Option<&'static str> {
+    match i {
+      0 => Some("hyst"),
+      1 => Some("comparator"),
+      2 => Some("b2f"),
+      _ => None
+    }
+  }
+}
+
+/// Blocks declared against the direction of their connections: hyst -> b2f -> comparator
+#[derive(dataflow_macros::System)]
+struct ReversedChain<'a> {
+  storage: &'a dyn SystemStorage,
+  comparator: comparator::Block<'a>,
+  #[parameters(false_value = 2.0)]
+  b2f: converter_b2f::Block<'a>,
+  hyst: hysteresis::Block<'a>,
+}
+
+impl<'a> ReversedChain<'a> {
+  fn connect(&mut self) {
+    self.comparator.in1.connect(&self.b2f.out1);
+    self.b2f.in1.connect(&self.hyst.out1);
+    self.hyst.in1.connect(&self.b2f.out1);
+  }
+}
+
+#[test]
+fn execution_order_follows_connections() {
+  let storage = DynamicStorage::new(ReversedChain::SIZE);
+  let system = ReversedChain::new(&storage).unwrap();
+  let computations = system.computations().unwrap();
+  // In declaration order the comparator would read b2f before it is computed
+  system.step(&computations);
+  assert_eq!(system.b2f.out1.get(), 2.0);
+  assert!(system.comparator.out1.get());
+}
+
+#[test]
+fn loop_through_state_block_is_scheduled() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  assert!(system.algebraic_loops().is_empty());
+  assert_eq!(system.computations().unwrap().len(), 5);
+}
+
+#[test]
+fn loop_through_state_only_output_is_scheduled() {
+  let storage = DynamicStorage::new(LoopSystem::SIZE);
+  let system = LoopSystem::new(&storage, false);
+  assert!(system.algebraic_loops().is_empty());
+  // hyst output, b2f output, comparator output, hyst state update
+  assert_eq!(system.computations().unwrap().len(), 4);
+}
+
+#[test]
+fn unread_outputs_are_warnings() {
+  let storage = DynamicStorage::new(LoopSystem::SIZE);
+  let system = LoopSystem::new(&storage, false);
+  let warnings = vec![ConnectionIssue::UnreadOutput { block: "comparator", output: "out1" }];
+  assert_eq!(system.validate(), Ok(warnings.clone()));
+  let summary = Simulator::new(Euler, 0.0, 1.0, 1.0).run(&system).unwrap();
+  assert_eq!(summary.warnings, warnings);
+}
+
+#[test]
+fn loop_through_feedthrough_blocks_is_reported() {
+  let storage = DynamicStorage::new(LoopSystem::SIZE);
+  let system = LoopSystem::new(&storage, true);
+  let loops = system.algebraic_loops();
+  assert_eq!(loops.len(), 1);
+  assert_eq!(
+    loops[0].to_string(),
+    "algebraic loop through [comparator, b2f]: comparator.out1 -> b2f.in1, b2f.out1 -> comparator.in1"
+  );
+}
+
+/// 1 kHz controller and 1 Hz supervisor, each followed by a block inheriting its sample time.
+/// With `closed`, the following blocks feed back into the controller and the supervisor.
+struct MultiRateSystem<'a> {
+  storage: &'a DynamicStorage,
+  controller: counter::Block<'a>,
+  supervisor: counter::Block<'a>,
+  filter: counter::Block<'a>,
+  logger: counter::Block<'a>,
+}
+
+impl<'a> MultiRateSystem<'a> {
+  const SIZE: SystemSize = SystemSize::new().add(counter::SIZE).add(counter::SIZE).add(counter::SIZE).add(counter::SIZE);
+
+  fn new(storage: &'a DynamicStorage, closed: bool) -> MultiRateSystem<'a> {
+    let mut counters = SystemCounters::new();
+    let mut system = MultiRateSystem {
+      storage,
+      controller: counter::new(storage, &mut counters, SampleTime::discrete(1e-3)),
+      supervisor: counter::new(storage, &mut counters, SampleTime::Discrete { period: 1.0, offset: 0.5 }),
+      filter: counter::new(storage, &mut counters, SampleTime::Inherited),
+      logger: counter::new(storage, &mut counters, SampleTime::Inherited),
+    };
+    system.filter.in1.connect(&system.controller.out1);
+    system.logger.in1.connect(&system.supervisor.out1);
+    if closed {
+      system.controller.in1.connect(&system.filter.out1);
+      system.supervisor.in1.connect(&system.logger.out1);
+    }
+    system
+  }
+}
+
+impl<'a> ISystem<'a> for MultiRateSystem<'a> {
+  const N_BLOCKS: usize = 4;
+
+  fn storage(&self) -> &dyn SystemStorage {
+    self.storage
+  }
+
+  fn block(&'a self, i: usize) -> Option<&'a dyn IBlock> {
+    match i {
+      0 => Some(&self.controller),
+      1 => Some(&self.supervisor),
+      2 => Some(&self.filter),
+      3 => Some(&self.logger),
+      _ => None
+    }
+  }
+
+  fn block_name(&self, i: usize) -> Option<&'static str> {
+    match i {
+      0 => Some("controller"),
+      1 => Some("supervisor"),
+      2 => Some("filter"),
+      3 => Some("logger"),
+      _ => None
+    }
+  }
+}
+
+#[test]
+fn blocks_run_at_their_sample_hits() {
+  let storage = DynamicStorage::new(MultiRateSystem::SIZE);
+  let system = MultiRateSystem::new(&storage, true);
+  assert_eq!(
+    system.dependency_graph().sample_times(),
+    vec![
+      SampleTime::discrete(1e-3),
+      SampleTime::Discrete { period: 1.0, offset: 0.5 },
+      SampleTime::discrete(1e-3),
+      SampleTime::Discrete { period: 1.0, offset: 0.5 },
+    ]
+  );
+
+  let summary = Simulator::new(Euler, 0.0, 3.0, 1.0).run(&system).unwrap();
+  assert_eq!(summary.final_time, 3.0);
+  assert_eq!(summary.steps, 3000);
+  // Hits at 0, 1 ms, ..., 3 s and at 0.5 s, 1.5 s, 2.5 s
+  assert_eq!(system.controller.calls.get(), 3001);
+  assert_eq!(system.filter.calls.get(), 3001);
+  assert_eq!(system.supervisor.calls.get(), 3);
+  assert_eq!(system.logger.calls.get(), 3);
+}
+
+#[test]
+fn unconnected_inputs_are_reported() {
+  let storage = DynamicStorage::new(MultiRateSystem::SIZE);
+  let system = MultiRateSystem::new(&storage, false);
+  let error = system.validate().unwrap_err();
+  assert_eq!(
+    error.unconnected_inputs,
+    vec![
+      ConnectionIssue::UnconnectedInput { block: "controller", input: "in1" },
+      ConnectionIssue::UnconnectedInput { block: "supervisor", input: "in1" },
+    ]
+  );
+  assert_eq!(error.warnings.len(), 2);
+  assert_eq!(error.to_string(), "2 unconnected input(s): controller.in1 is not connected, supervisor.in1 is not connected");
+}
+
+#[test]
+fn invalid_sample_times_are_reported() {
+  for sample_time in [
+    SampleTime::discrete(0.0),
+    SampleTime::discrete(-1.0),
+    SampleTime::discrete(f64::NAN),
+    SampleTime::Discrete { period: 1.0, offset: f64::INFINITY },
+  ] {
+    let storage = DynamicStorage::new(MultiRateSystem::SIZE);
+    let mut system = MultiRateSystem::new(&storage, true);
+    system.supervisor.sample_time = sample_time;
+    let error = system.validate().unwrap_err();
+    assert_eq!((error.unconnected_inputs.len(), error.invalid_sample_times), (0, vec!["supervisor"]));
+    assert!(matches!(Simulator::new(Euler, 0.0, 1.0, 1.0).run(&system), Err(Error::Validation(_))));
+  }
+}
@@ -0,0 +1,282 @@
+use dataflow_core::block::Access;
+use dataflow_core::solver::{Integrator, Euler, Heun, RungeKutta4, DormandPrince, Bdf};
+use dataflow_core::system::{ISystem, SystemStorage, SystemStorageItemId, DynamicStorage};
+use dataflow_core::simulator::Simulator;
+use dataflow_core::writer::{IWriter, WriterResult};
+use dataflow_core::Error;
+use dataflow::examples::temperature_controller;
+
+/// The example system with the heating off, its thermal mass cools down to ambient temperature,
+/// T(t) = 20 + 80 exp(-t / 100)
+fn cooling(storage: &DynamicStorage) -> temperature_controller::SystemImpl<'_> {
+  let system = temperature_controller::SystemImpl::new(storage).unwrap();
+  system.thermal_mass.cp.set(1000.0);
+  system.thermal_mass.t.set(100.0);
+  system.hyst_component.low_threshold.set(-1000.0);
+  system.hyst_component.high_threshold.set(1000.0);
+  system.hyst_component.out_inverted.set(false);
+  system
+}
+
+fn exact(t: f64) -> f64 {
+  20.0 + 80.0 * (-t / 100.0).exp()
+}
+
+/// Integrates over 100 s with step `dt` and returns the final error
+fn error_after<'a, S: ISystem<'a>, I: Integrator>(
+  system: &'a S, temperature: &dyn Fn() -> f64, integrator: &mut I, dt: f64
+) -> f64 {
+  let computations = system.computations().unwrap();
+  let n = (100.0 / dt).round() as usize;
+  for _ in 0..n {
+    system.step(&computations);
+    system.integrate(integrator, &computations, dt).unwrap();
+  }
+  (temperature() - exact(100.0)).abs()
+}
+
+fn convergence_order<I: Integrator>(mut integrator: I) -> f64 {
+  let mut errors = vec![];
+  for dt in [10.0, 5.0] {
+    let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+    let system = cooling(&storage);
+    errors.push(error_after(&system, &|| system.thermal_mass.t.get(), &mut integrator, dt));
+  }
+  (errors[0] / errors[1]).log2()
+}
+
+#[test]
+fn fixed_step_integrators() {
+  assert!((convergence_order(Euler) - 1.0).abs() < 0.15);
+  assert!((convergence_order(Heun) - 2.0).abs() < 0.15);
+  assert!((convergence_order(RungeKutta4) - 4.0).abs() < 0.15);
+}
+
+#[test]
+fn adaptive_step_meets_tolerance_at_output_times() {
+  let mut steps = vec![];
+  for rtol in [1e-4, 1e-8] {
+    let mut integrator = DormandPrince::new(rtol, 1e-10);
+    let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+    let system = cooling(&storage);
+    let computations = system.computations().unwrap();
+    for i in 1..=4 {
+      system.step(&computations);
+      system.integrate(&mut integrator, &computations, 25.0).unwrap();
+      let error = (system.thermal_mass.t.get() - exact(25.0 * i as f64)).abs();
+      assert!(error < 100.0 * rtol, "error {} at rtol {}", error, rtol);
+    }
+    steps.push(integrator.accepted_steps);
+  }
+  assert!(steps[0] < steps[1]);
+}
+
+#[test]
+fn bdf_accuracy_and_order_selection() {
+  let mut integrator = Bdf::new(1e-7, 1e-10);
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = cooling(&storage);
+  let computations = system.computations().unwrap();
+  let mut max_order = 0;
+  for i in 1..=10 {
+    system.step(&computations);
+    system.integrate(&mut integrator, &computations, 10.0).unwrap();
+    max_order = max_order.max(integrator.order);
+    let error = (system.thermal_mass.t.get() - exact(10.0 * i as f64)).abs();
+    assert!(error < 1e-3, "error {} at t = {}", error, 10.0 * i as f64);
+  }
+  assert!(max_order > 1);
+}
+
+#[test]
+fn bdf_is_stable_on_stiff_system() {
+  // Time constant of 0.01 s, output every 10 s
+  let mut integrator = Bdf::new(1e-6, 1e-9);
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = cooling(&storage);
+  system.thermal_mass.cp.set(0.1);
+  let computations = system.computations().unwrap();
+  for _ in 0..10 {
+    system.step(&computations);
+    system.integrate(&mut integrator, &computations, 10.0).unwrap();
+  }
+  assert!((system.thermal_mass.t.get() - 20.0).abs() < 1e-6);
+  assert!(integrator.accepted_steps < 500, "{} steps", integrator.accepted_steps);
+  // The Jacobian of the linear system is reused instead of evaluated at every step attempt
+  assert!(integrator.jacobian_evaluations < 10, "{:?}", integrator.statistics());
+}
+
+#[derive(Default)]
+struct RecordingWriter {
+  samples: Vec<(f64, f64)>,
+}
+
+impl IWriter for RecordingWriter {
+  fn add_field<T: Into<SystemStorageItemId>>(&mut self, _field: T) {}
+
+  fn write_step(&mut self, storage: &dyn SystemStorage, time: f64) -> WriterResult<()> {
+    self.samples.push((time, storage.r_state_get(0)));
+    Ok(())
+  }
+}
+
+#[test]
+fn simulator_writes_at_output_times() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = cooling(&storage);
+  let mut writer = RecordingWriter::default();
+  let mut simulator = Simulator::new(RungeKutta4, 0.0, 100.0, 30.0);
+  simulator.dt = 1.0;
+  simulator.add_writer(&mut writer);
+  let summary = simulator.run(&system).unwrap();
+
+  assert_eq!(summary.final_time, 100.0);
+  assert_eq!(summary.steps, 100);
+  assert!(summary.errors.is_empty());
+  let times: Vec<f64> = writer.samples.iter().map(|x| x.0).collect();
+  assert_eq!(times, vec![0.0, 30.0, 60.0, 90.0, 100.0]);
+  for (t, temperature) in writer.samples {
+    assert!((temperature - exact(t)).abs() < 1e-6);
+  }
+}
+
+#[test]
+fn simulator_locates_switching_time() {
+  // Heating towards 70 °C with a time constant of 400 s until 32 °C are reached, then cooling
+  let t_switch = 400.0 * (50.0f64 / 38.0).ln();
+  let expected = |t: f64| if t < t_switch {
+    70.0 - 50.0 * (-t / 400.0).exp()
+  } else {
+    20.0 + 12.0 * (-(t - t_switch) / 400.0).exp()
+  };
+
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  let mut writer = RecordingWriter::default();
+  let mut simulator = Simulator::new(RungeKutta4, 0.0, 180.0, 5.0);
+  simulator.dt = 1.0;
+  simulator.add_writer(&mut writer);
+  let summary = simulator.run(&system).unwrap();
+
+  // Rising through both thresholds, then falling through the upper one
+  assert_eq!(summary.events, 3);
+  assert_eq!(writer.samples.len(), 37);
+  for (t, temperature) in writer.samples {
+    assert!((temperature - expected(t)).abs() < 1e-6, "{} instead of {} at t = {}", temperature, expected(t), t);
+  }
+}
+
+#[test]
+fn simulator_stops_at_non_finite_state() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = cooling(&storage);
+  system.thermal_mass.cp.set(0.0);
+  match Simulator::new(Euler, 0.0, 100.0, 10.0).run(&system) {
+    Err(Error::NonFinite { time, state, value }) => {
+      assert_eq!((time, state), (10.0, 0));
+      assert!(value.is_infinite());
+    },
+    other => panic!("unexpected result {:?}", other),
+  }
+}
+
+#[test]
+fn bdf_reports_newton_failure() {
+  use dataflow_core::system::SystemSize;
+  let storage = DynamicStorage::new(SystemSize { r_state: 1, ..SystemSize::new() });
+  // Derivatives that are not finite away from the initial state
+  let result = Bdf::default().advance(&storage, &mut || storage.r_state_der_set(0, f64::NAN), 1.0);
+  assert!(matches!(result, Err(Error::Numerical(_))), "{:?}", result);
+}
+
+#[test]
+fn dormand_prince_reports_non_finite_error_estimate() {
+  use dataflow_core::system::SystemSize;
+  let storage = DynamicStorage::new(SystemSize { r_state: 1, ..SystemSize::new() });
+  let mut integrator = DormandPrince::default();
+  let result = integrator.advance(&storage, &mut || storage.r_state_der_set(0, f64::NAN), 1.0);
+  assert!(matches!(result, Err(Error::Numerical(_))), "{:?}", result);
+  // Restored to the start of the failed step
+  assert_eq!(storage.r_state_get(0), 0.0);
+  assert_eq!(integrator.accepted_steps, 0);
+}
+
+#[test]
+fn dormand_prince_reports_tolerance_failure_at_minimum_step() {
+  use dataflow_core::system::SystemSize;
+  let storage = DynamicStorage::new(SystemSize { r_state: 1, ..SystemSize::new() });
+  let mut integrator = DormandPrince::default();
+  // Stages that disagree by far more than the tolerances allow at any step size
+  let mut stage = 0;
+  let result = integrator.advance(&storage, &mut || {
+    stage += 1;
+    storage.r_state_der_set(0, if stage % 2 == 0 { 1e9 } else { -1e9 });
+  }, 1.0);
+  assert!(matches!(result, Err(Error::Numerical(_))), "{:?}", result);
+  assert_eq!((storage.r_state_get(0), integrator.accepted_steps), (0.0, 0));
+}
+
+#[test]
+fn event_location_does_not_count_trial_steps() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  let mut simulator = Simulator::new(DormandPrince::new(1e-3, 1e-6), 0.0, 180.0, 5.0);
+  simulator.dt = 1.0;
+  let summary = simulator.run(&system).unwrap();
+  assert_eq!(summary.events, 3);
+  // The bisections add about 30 steps per event otherwise
+  assert!(summary.statistics.accepted_steps < 30, "{:?}", summary.statistics);
+  assert_eq!(summary.statistics.accepted_steps, simulator.integrator.accepted_steps);
+}
+
+#[test]
+fn simulator_rejects_invalid_settings() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  let invalid = |simulator: &mut Simulator<Euler>| matches!(simulator.run(&system), Err(Error::Settings(_)));
+
+  assert!(invalid(&mut Simulator::new(Euler, 0.0, 10.0, 0.0)));
+  assert!(invalid(&mut Simulator::new(Euler, 0.0, 10.0, f64::NAN)));
+  assert!(invalid(&mut Simulator::new(Euler, 10.0, 0.0, 1.0)));
+  assert!(invalid(&mut Simulator::new(Euler, 0.0, f64::INFINITY, 1.0)));
+  let mut simulator = Simulator::new(Euler, 0.0, 10.0, 1.0);
+  simulator.dt = -1.0;
+  assert!(invalid(&mut simulator));
+  simulator.dt = 1.0;
+  simulator.event_tolerance = Some(0.0);
+  assert!(invalid(&mut simulator));
+  simulator.event_tolerance = None;
+  assert!(simulator.run(&system).is_ok());
+}
+
+#[test]
+fn dense_output_does_not_limit_step_size() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = cooling(&storage);
+  let mut writer = RecordingWriter::default();
+  let mut simulator = Simulator::new(DormandPrince::new(1e-8, 1e-10), 0.0, 100.0, 0.1);
+  simulator.add_writer(&mut writer);
+  let summary = simulator.run(&system).unwrap();
+  let max_error = writer.samples.iter().map(|(t, x)| (x - exact(*t)).abs()).fold(0.0, f64::max);
+  assert_eq!(writer.samples.len(), 1001);
+  assert!(max_error < 1e-6, "error {}", max_error);
+  // Steps much longer than the output interval, interpolated at the output times
+  assert!(summary.statistics.accepted_steps < 50, "{:?}", summary.statistics);
+}
+
+#[test]
+fn dense_output_restarts_after_system_change() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = cooling(&storage);
+  let computations = system.computations().unwrap();
+  let mut integrator = DormandPrince::new(1e-8, 1e-10);
+  system.step(&computations);
+  system.integrate(&mut integrator, &computations, 25.0).unwrap();
+  // Continuing the step taken for the old ambient temperature would miss the change
+  let t_25 = system.thermal_mass.t.get();
+  system.thermal_mass.t_amb.set(50.0);
+  system.step(&computations);
+  system.integrate(&mut integrator, &computations, 25.0).unwrap();
+  let expected = 50.0 + (t_25 - 50.0) * (-0.25f64).exp();
+  assert!((system.thermal_mass.t.get() - expected).abs() < 1e-5);
+}
@@ -0,0 +1,559 @@
+use dataflow_core::block::*;
+use dataflow_core::solver::RungeKutta4;
+use dataflow_core::simulator::Simulator;
+use dataflow_core::writer::{CSVWriter, IWriter};
+use dataflow_core::Error;
+use dataflow_core::snapshot::Snapshot;
+use dataflow_core::params::ParameterSet;
+use dataflow_core::system::{SystemStorage, ISystem, ParameterReset, ItemKind, DType, Value, SystemCounters, SystemSize, SystemStorageItemId, DynamicStorage};
+use dataflow::examples::temperature_controller;
+
+/// The example system with the heating off, its thermal mass cools down to ambient temperature
+/// from `t0`, on storage owned by the caller
+fn cooling(storage: &DynamicStorage, t0: f64) -> temperature_controller::SystemImpl<'_> {
+  let system = temperature_controller::SystemImpl::new(storage).unwrap();
+  system.thermal_mass.cp.set(1000.0);
+  system.thermal_mass.t.set(t0);
+  system.hyst_component.low_threshold.set(-1000.0);
+  system.hyst_component.high_threshold.set(1000.0);
+  system.hyst_component.out_inverted.set(false);
+  system
+}
+
+/// File in the temporary directory named after the test and the process, as tests run in parallel
+fn temp_path(name: &str) -> std::path::PathBuf {
+  std::env::temp_dir().join(format!("dataflow-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn dynamic_storage_instances_are_independent() {
+  let storage1 = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let storage2 = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  assert_eq!(storage1.sizes(), temperature_controller::SystemImpl::SIZE);
+
+  let system1 = cooling(&storage1, 100.0);
+  let system2 = cooling(&storage2, 50.0);
+  for system in [&system1, &system2] {
+    let computations = system.computations().unwrap();
+    for _ in 0..100 {
+      system.step(&computations);
+      system.integrate(&mut RungeKutta4, &computations, 1.0).unwrap();
+    }
+  }
+
+  let exact = |t0: f64| 20.0 + (t0 - 20.0) * (-1.0f64).exp();
+  assert!((system1.thermal_mass.t.get() - exact(100.0)).abs() < 1e-6);
+  assert!((system2.thermal_mass.t.get() - exact(50.0)).abs() < 1e-6);
+}
+
+#[test]
+fn systems_run_on_separate_threads() {
+  let storage1 = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let storage2 = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system1 = cooling(&storage1, 100.0);
+  let system2 = cooling(&storage2, 50.0);
+
+  std::thread::scope(|scope| {
+    for system in [&system1, &system2] {
+      scope.spawn(move || {
+        let computations = system.computations().unwrap();
+        for _ in 0..100 {
+          system.step(&computations);
+          system.integrate(&mut RungeKutta4, &computations, 1.0).unwrap();
+        }
+      });
+    }
+  });
+
+  let exact = |t0: f64| 20.0 + (t0 - 20.0) * (-1.0f64).exp();
+  assert!((system1.thermal_mass.t.get() - exact(100.0)).abs() < 1e-6);
+  assert!((system2.thermal_mass.t.get() - exact(50.0)).abs() < 1e-6);
+}
+
+#[test]
+fn instances_of_the_same_system_do_not_interfere() {
+  let run = |system: &temperature_controller::SystemImpl| {
+    Simulator::new(RungeKutta4, 0.0, 500.0, 5.0).run(system).unwrap();
+    system.thermal_mass.t.get()
+  };
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let alone = run(&temperature_controller::SystemImpl::new(&storage).unwrap());
+
+  let storages: Vec<DynamicStorage> = (0..3).map(|_| DynamicStorage::new(temperature_controller::SystemImpl::SIZE)).collect();
+  let rooms: Vec<temperature_controller::SystemImpl> = storages.iter()
+    .map(|storage| temperature_controller::SystemImpl::new(storage).unwrap())
+    .collect();
+  rooms[1].hyst_component.low_threshold.set(20.0);
+  rooms[1].hyst_component.high_threshold.set(22.0);
+  rooms[2].b2f.true_value.set(0.0);
+
+  let temperatures: Vec<f64> = std::thread::scope(|scope| {
+    let handles: Vec<_> = rooms.iter().map(|room| scope.spawn(move || run(room))).collect();
+    handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+  });
+  assert_eq!(temperatures[0], alone);
+  assert!(temperatures[1] < 23.0);
+  assert_eq!(temperatures[2], 20.0);
+}
+
+#[test]
+fn writer_errors_are_reported() {
+  assert!(matches!(CSVWriter::new("does/not/exist.csv"), Err(Error::Io(_))));
+
+  let path = temp_path("writer_errors_are_reported.csv");
+  let mut writer = CSVWriter::new(path.to_str().unwrap()).unwrap();
+  writer.add_field(SystemStorageItemId::RealState(1));
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  match writer.write_step(&storage, 0.0) {
+    Err(Error::StorageIndex { id, size }) => assert_eq!((id, size), (SystemStorageItemId::RealState(1), 1)),
+    other => panic!("unexpected result {:?}", other),
+  }
+}
+
+#[test]
+fn integer_lane() {
+  let storage = DynamicStorage::new(SystemSize { i_param: 1, i_state: 1, i_out: 1, ..SystemSize::new() });
+  let mut counters = SystemCounters::new();
+  let n_modes = Parameter::<i64>::new(&storage, counters.next_i_param()).init(3);
+  let switches = DiscreteState::<i64>::new(&storage, counters.next_i_state()).init(-2);
+  let mode = Output::<i64>::new(&storage, counters.next_i_out());
+  let mut request = Input::<i64>::new(&storage);
+  request.connect(&mode);
+
+  mode.set(i64::MAX);
+  switches.set(switches.get() + 1);
+  assert_eq!((n_modes.get(), switches.get(), request.get()), (3, -1, i64::MAX));
+  assert_eq!(request.source(), Some(SystemStorageItemId::IntOutput(0)));
+  assert_eq!(SystemStorageItemId::from(&switches), SystemStorageItemId::IntState(0));
+  assert!(storage.check(SystemStorageItemId::IntParameter(1)).is_err());
+}
+
+#[test]
+fn real_discrete_state_lane() {
+  let storage = DynamicStorage::new(SystemSize { r_state: 1, r_dstate: 2, ..SystemSize::new() });
+  let mut counters = SystemCounters::new();
+  let _held = DiscreteState::<f64>::new(&storage, counters.next_r_dstate()).init(1.5);
+  let accumulated = DiscreteState::<f64>::new(&storage, counters.next_r_dstate()).init(0.25);
+  accumulated.set(accumulated.get() * 2.0);
+
+  assert_eq!((storage.r_dstate_get(0), storage.r_dstate_get(1)), (1.5, 0.5));
+  assert_eq!(storage.r_state_get(0), 0.0);
+  assert_eq!(SystemStorageItemId::from(&accumulated), SystemStorageItemId::RealDiscreteState(1));
+}
+
+#[test]
+fn snapshot_and_restore() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  Simulator::new(RungeKutta4, 0.0, 200.0, 5.0).run(&system).unwrap();
+  let checkpoint = storage.snapshot(200.0);
+  assert_eq!(checkpoint.sizes(), temperature_controller::SystemImpl::SIZE);
+  assert_eq!(checkpoint.time(), 200.0);
+
+  let resume = |system: &temperature_controller::SystemImpl| {
+    Simulator::new(RungeKutta4, checkpoint.time(), 400.0, 5.0).run(system).unwrap();
+    system.thermal_mass.t.get()
+  };
+  let baseline = resume(&system);
+
+  // What-if: no heating at all, then roll back
+  storage.restore(&checkpoint).unwrap();
+  system.b2f.true_value.set(0.0);
+  assert!(resume(&system) < baseline);
+  storage.restore(&checkpoint).unwrap();
+  assert_eq!(resume(&system), baseline);
+
+  // Resume from a file, on a new instance
+  let path = temp_path("snapshot_and_restore.csv");
+  checkpoint.save(path.to_str().unwrap()).unwrap();
+  let loaded = Snapshot::load(path.to_str().unwrap()).unwrap();
+  assert_eq!(loaded, checkpoint);
+  assert_eq!(loaded.time(), 200.0);
+  let storage2 = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system2 = temperature_controller::SystemImpl::new(&storage2).unwrap();
+  storage2.restore(&loaded).unwrap();
+  assert_eq!(resume(&system2), baseline);
+
+  let other = DynamicStorage::new(SystemSize { r_state: 1, ..SystemSize::new() });
+  assert!(matches!(other.restore(&checkpoint), Err(Error::SizeMismatch { .. })));
+}
+
+#[test]
+fn reset_to_initial_conditions() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  let run = |system: &temperature_controller::SystemImpl| {
+    Simulator::new(RungeKutta4, 0.0, 300.0, 5.0).run(system).unwrap();
+    system.thermal_mass.t.get()
+  };
+  let first = run(&system);
+  assert_ne!(system.thermal_mass.t.get(), 20.0);
+
+  system.thermal_mass.h.set(5.0);
+  system.b2f.true_value.set(1000.0);
+  system.reset(ParameterReset::KeepOverrides);
+  assert_eq!(system.thermal_mass.t.get(), 20.0);
+  assert!(!system.hyst_component.state_high.get());
+  assert_eq!((system.thermal_mass.h.get(), system.b2f.true_value.get()), (10.0, 500.0));
+  assert_eq!(system.thermal_mass.cp.get(), 4000.0);
+  assert_eq!(run(&system), first);
+
+  system.reset(ParameterReset::BlockInitials);
+  assert_eq!(system.thermal_mass.cp.get(), 1.0);
+  assert_eq!(system.hyst_component.high_threshold.get(), 1.0);
+  assert!(!system.hyst_component.out_inverted.get());
+  assert_eq!(system.thermal_mass.t.get(), 20.0);
+}
+
+#[test]
+fn registry_paths() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  let registry = system.registry();
+
+  let cp = registry.get("thermal_mass.cp").unwrap();
+  assert_eq!(cp, SystemStorageItemId::from(&system.thermal_mass.cp));
+  assert_eq!((cp.kind(), cp.dtype()), (ItemKind::Parameter, DType::Real));
+  let state_high = registry.get("hyst_component.state_high").unwrap();
+  assert_eq!((state_high.kind(), state_high.dtype()), (ItemKind::DiscreteState, DType::Bool));
+  assert_eq!(registry.get("b2f.out1").map(|id| id.kind()), Some(ItemKind::Output));
+  assert_eq!(registry.get("thermal_mass.missing"), None);
+  assert_eq!(registry.path(SystemStorageItemId::RealState(0)), Some("thermal_mass.t"));
+  assert_eq!(registry.iter().count(), 14);
+
+  let dump = registry.dump(&storage);
+  assert!(dump.contains("thermal_mass.cp = 4000\n"));
+  assert!(dump.contains("hyst_component.out_inverted = true\n"));
+
+  // Writers address signals by path
+  let path = temp_path("registry_paths.csv");
+  let mut writer = CSVWriter::new(path.to_str().unwrap()).unwrap();
+  writer.add_field(registry.get("thermal_mass.t").unwrap());
+  writer.write_step(&storage, 0.0).unwrap();
+  assert_eq!(std::fs::read_to_string(&path).unwrap(), "0,20\n");
+}
+
+#[test]
+fn parameter_files() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  let registry = system.registry();
+
+  let exported = ParameterSet::from_storage(&registry, &storage);
+  assert_eq!(exported.get("thermal_mass.cp"), Some(Value::Real(4000.0)));
+  assert_eq!(exported.get("thermal_mass.t"), None);
+  let text = exported.to_toml().unwrap();
+  assert!(text.contains("[thermal_mass]\narea = 1.0\ncp = 4000.0\n"), "{}", text);
+  assert_eq!(ParameterSet::from_toml(&text).unwrap(), exported);
+  assert_eq!(ParameterSet::from_json(&exported.to_json().unwrap()).unwrap(), exported);
+
+  let tuning = ParameterSet::from_toml("[thermal_mass]\ncp = 3000\n[hyst_component]\nout_inverted = false\n").unwrap();
+  tuning.apply(&registry, &storage).unwrap();
+  assert_eq!(system.thermal_mass.cp.get(), 3000.0);
+  assert!(!system.hyst_component.out_inverted.get());
+
+  let path = temp_path("parameter_files.json");
+  exported.save(path.to_str().unwrap()).unwrap();
+  ParameterSet::load(path.to_str().unwrap()).unwrap().apply(&registry, &storage).unwrap();
+  assert_eq!(ParameterSet::from_storage(&registry, &storage), exported);
+
+  let apply = |text: &str| ParameterSet::from_toml(text).unwrap().apply(&registry, &storage);
+  assert!(matches!(apply("[thermal_mass]\ncp = 1.0\nmass = 2.0\n"),
+    Err(Error::UnknownParameter(path)) if path == "thermal_mass.mass"));
+  assert!(matches!(apply("[thermal_mass]\nt = 25.0\n"), Err(Error::UnknownParameter(_))));
+  assert!(matches!(apply("[thermal_mass]\ncp = 1.0\nh = true\n"),
+    Err(Error::TypeMismatch { path, expected: DType::Real }) if path == "thermal_mass.h"));
+  assert_eq!(system.thermal_mass.cp.get(), 4000.0);
+  assert!(ParameterSet::from_toml("[thermal_mass]\ncp = \"hot\"\n").is_err());
+  assert!(ParameterSet::load("parameters.yaml").is_err());
+
+  // Non-finite values cannot be saved instead of coming back as null
+  let mut unbounded = exported.clone();
+  unbounded.insert("thermal_mass.h", Value::Real(f64::INFINITY));
+  assert!(unbounded.to_json().is_err() && unbounded.to_toml().is_err());
+  unbounded.insert("thermal_mass.h", Value::Real(f64::NAN));
+  assert!(unbounded.save(temp_path("parameter_files_nan.json").to_str().unwrap()).is_err());
+}
+
+#[test]
+fn parameter_round_trip() {
+  let storage = DynamicStorage::new(temperature_controller::SystemImpl::SIZE);
+  let system = temperature_controller::SystemImpl::new(&storage).unwrap();
+  let registry = system.registry();
+  system.thermal_mass.cp.set(1e-300);
+  system.thermal_mass.t_amb.set(-0.1);
+  let exported = ParameterSet::from_storage(&registry, &storage);
+  assert_eq!(ParameterSet::from_json(&exported.to_json().unwrap()).unwrap(), exported);
+  assert_eq!(ParameterSet::from_toml(&exported.to_toml().unwrap()).unwrap(), exported);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+  Idle,
+  Heating,
+  Cooling,
+}
+
+impl StateEnum for Mode {
+  fn to_i64(self) -> i64 {
+    self as i64
+  }
+  fn from_i64(v: i64) -> Option<Self> {
+    match v {
+      0 => Some(Mode::Idle),
+      1 => Some(Mode::Heating),
+      2 => Some(Mode::Cooling),
+      _ => None
+    }
+  }
+  fn variant_name(v: i64) -> Option<&'static str> {
+    ["Idle", "Heating", "Cooling"].get(usize::try_from(v).ok()?).copied()
+  }
+}
+
+#[test]
+fn enum_state_lane() {
+  let storage = DynamicStorage::new(SystemSize { i_state: 2, ..SystemSize::new() });
+  let mut counters = SystemCounters::new();
+  let mode = DiscreteState::<Mode>::new(&storage, counters.next_i_state()).init(Mode::Idle);
+  let count = DiscreteState::<i64>::new(&storage, counters.next_i_state()).init(0);
+
+  let path = temp_path("enum_state_lane.csv");
+  let mut writer = CSVWriter::new(path.to_str().unwrap()).unwrap();
+  writer.add_enum_field(&mode);
+  writer.add_field(&count);
+  for (i, temperature) in [20.0, 16.0, 20.0, 26.0].into_iter().enumerate() {
+    let next = match mode.get() {
+      Mode::Idle if temperature < 17.0 => Mode::Heating,
+      Mode::Idle if temperature > 25.0 => Mode::Cooling,
+      Mode::Heating if temperature > 19.0 => Mode::Idle,
+      Mode::Cooling if temperature < 23.0 => Mode::Idle,
+      current => current,
+    };
+    if next != mode.get() {
+      count.set(count.get() + 1);
+    }
+    mode.set(next);
+    writer.write_step(&storage, i as f64).unwrap();
+  }
+
+  assert_eq!(storage.i_state_get(0), Mode::Cooling as i64);
+  assert_eq!(SystemStorageItemId::from(&mode), SystemStorageItemId::IntState(0));
+  let csv = std::fs::read_to_string(&path).unwrap();
+  assert_eq!(csv.lines().collect::<Vec<_>>(), ["0,Idle,0", "1,Heating,1", "2,Idle,2", "3,Cooling,3"]);
+}
+
+/// Point mass with velocity dependent damping, v' = (force - damping v) / mass
+mod point_mass {
+  use dataflow_core::block::*;
+  use dataflow_core::system::{SystemStorage, SystemCounters};
+
+  pub struct Block<'a> {
+    pub mass: Parameter<'a, f64>,
+    pub damping: Parameter<'a, [[f64; 3]; 3]>,
+    pub force: Input<'a, [f64; 3]>,
+    pub v: ContinuousState<'a, [f64; 3]>,
+    pub v_out: Output<'a, [f64; 3]>,
+  }
+
+  pub const SIZE: BlockSize = BlockSize {
+    r_param: 10,
+    r_state: 3,
+    r_in: 3,
+    r_out: 3,
+    ..BlockSize::new()
+  };
+
+  pub fn new<'a>(storage: &'a dyn SystemStorage, counters: &mut SystemCounters) -> Block<'a> {
+    Block {
+      mass: Parameter::new(storage, counters.next_r_param()).init(1.0),
+      damping: Parameter::new(storage, counters.next_r_param_n(9)).init([[0.0; 3]; 3]),
+      force: Input::new(storage),
+      v: ContinuousState::new(storage, counters.next_r_state_n(3)).init([0.0; 3]),
+      v_out: Output::new(storage, counters.next_r_out_n(3)),
+    }
+  }
+
+  impl<'a> ApplyOutputUpdate for Block<'a> {
+    fn apply(&self) {
+      self.v_out.set(self.v.get());
+    }
+  }
+
+  impl<'a> ApplyStateUpdate for Block<'a> {
+    fn apply(&self) {
+      let (force, damping, v) = (self.force.get(), self.damping.get(), self.v.get());
+      self.v.der_set(std::array::from_fn(|i| {
+        let friction: f64 = (0..3).map(|j| damping[i][j] * v[j]).sum();
+        (force[i] - friction) / self.mass.get()
+      }));
+    }
+  }
+
+  impl<'a> IBlock for Block<'a> {
+    fn get_computation(&self) -> BlockComputation<'_> {
+      BlockComputation::State(StateBlockComputation {
+        state_update_fn: StateUpdateFunction { f: self },
+        output_update_fn: OutputUpdateFunction { f: self },
+      })
+    }
+
+    fn input_ports(&self) -> Vec<InputPort> {
+      vec![InputPort::new("force", self.force.source())]
+    }
+
+    fn output_ports(&self) -> Vec<OutputPort> {
+      vec![OutputPort::new("v_out", (&self.v_out).into())]
+    }
+
+    fn named_items(&self) -> Vec<NamedItem> {
+      vec![
+        NamedItem::new("mass", &self.mass),
+        NamedItem::array("damping", self.damping.items()),
+        NamedItem::array("v", self.v.items()),
+        NamedItem::array("v_out", self.v_out.items()),
+      ]
+    }
+  }
+}
+
+/// Constant vector signal
+mod source {
+  use dataflow_core::block::*;
+  use dataflow_core::system::{SystemStorage, SystemCounters};
+
+  pub struct Block<'a> {
+    pub value: Parameter<'a, [f64; 3]>,
+    pub out1: Output<'a, [f64; 3]>,
+  }
+
+  pub const SIZE: BlockSize = BlockSize {
+    r_param: 3,
+    r_out: 3,
+    ..BlockSize::new()
+  };
+
+  pub fn new<'a>(storage: &'a dyn SystemStorage, counters: &mut SystemCounters) -> Block<'a> {
+    Block {
+      value: Parameter::new(storage, counters.next_r_param_n(3)).init([0.0; 3]),
+      out1: Output::new(storage, counters.next_r_out_n(3)),
+    }
+  }
+
+  impl<'a> ApplyOutputUpdate for Block<'a> {
+    fn apply(&self) {
+      self.out1.set(self.value.get());
+    }
+  }
+
+  impl<'a> IBlock for Block<'a> {
+    fn get_computation(&self) -> BlockComputation<'_> {
+      BlockComputation::Functional(FunctionalBlockComputation {
+        output_update_fn: OutputUpdateFunction { f: self }
+      })
+    }
+
+    fn input_ports(&self) -> Vec<InputPort> {
+      vec![]
+    }
+
+    fn output_ports(&self) -> Vec<OutputPort> {
+      vec![OutputPort::new("out1", (&self.out1).into())]
+    }
+  }
+}
+
+struct Mechanics<'a> {
+  storage: &'a DynamicStorage,
+  source: source::Block<'a>,
+  mass: point_mass::Block<'a>,
+}
+
+impl<'a> Mechanics<'a> {
+  fn new(storage: &'a DynamicStorage) -> Mechanics<'a> {
+    let mut counters = SystemCounters::new();
+    let mut system = Mechanics {
+      storage,
+      source: source::new(storage, &mut counters),
+      mass: point_mass::new(storage, &mut counters),
+    };
+    system.mass.force.connect(&system.source.out1);
+    system
+  }
+}
+
+impl<'a> ISystem<'a> for Mechanics<'a> {
+  const N_BLOCKS: usize = 2;
+
+  fn storage(&self) -> &dyn SystemStorage {
+    self.storage
+  }
+
+  fn block(&'a self, i: usize) -> Option<&'a dyn IBlock> {
+    match i {
+      0 => Some(&self.source),
+      1 => Some(&self.mass),
+      _ => None
+    }
+  }
+
+  fn block_name(&self, i: usize) -> Option<&'static str> {
+    match i {
+      0 => Some("source"),
+      1 => Some("mass"),
+      _ => None
+    }
+  }
+}
+
+#[test]
+fn array_signals() {
+  let storage = DynamicStorage::new(SystemSize::new().add(source::SIZE).add(point_mass::SIZE));
+  let system = Mechanics::new(&storage);
+  system.source.value.set([1.0, -2.0, 4.0]);
+  system.mass.mass.set(2.0);
+  system.mass.damping.set([[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 4.0]]);
+  assert_eq!(system.mass.damping.get()[1], [0.0, 2.0, 0.0]);
+
+  let path = temp_path("array_signals.csv");
+  let mut writer = CSVWriter::new(path.to_str().unwrap()).unwrap();
+  writer.add_fields(system.mass.v_out.items());
+  let mut simulator = Simulator::new(RungeKutta4, 0.0, 1.0, 1.0);
+  simulator.dt = 0.01;
+  simulator.add_writer(&mut writer);
+  simulator.run(&system).unwrap();
+
+  // Each axis approaches force / damping with time constant mass / damping
+  let v = system.mass.v.get();
+  for (i, (force, damping)) in [(1.0, 1.0), (-2.0, 2.0), (4.0, 4.0)].into_iter().enumerate() {
+    let exact = force / damping * (1.0 - f64::exp(-damping / 2.0));
+    assert!((v[i] - exact).abs() < 1e-8, "{} instead of {}", v[i], exact);
+  }
+  let csv = std::fs::read_to_string(&path).unwrap();
+  let last: Vec<f64> = csv.lines().last().unwrap().split(',').map(|x| x.parse().unwrap()).collect();
+  assert_eq!(last.len(), 4);
+  assert_eq!(last[1..], v);
+
+  let registry = system.registry();
+  assert_eq!(registry.get("mass.v[2]"), Some(SystemStorageItemId::RealState(2)));
+  assert_eq!(registry.get("mass.damping[4]"), Some(SystemStorageItemId::RealParameter(3 + 1 + 4)));
+  assert_eq!(registry.get("mass.v"), None);
+  // Blocks without named items expose their outputs
+  assert_eq!(registry.get("source.out1"), Some(SystemStorageItemId::RealOutput(0)));
+
+  // Matrices are read from nested lists and written as flat ones
+  let damping = "{\"mass\": {\"damping\": [[2, 0, 0], [0, 3, 0], [0, 0, 4]], \"mass\": 1}}";
+  ParameterSet::from_json(damping).unwrap().apply(&registry, &storage).unwrap();
+  assert_eq!(system.mass.damping.get(), [[2.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, 4.0]]);
+  let exported = ParameterSet::from_storage(&registry, &storage);
+  assert_eq!(ParameterSet::from_toml(&exported.to_toml().unwrap()).unwrap(), exported);
+  assert!(exported.to_toml().unwrap().contains("damping = [2.0, 0.0, 0.0, 0.0, 3.0, 0.0, 0.0, 0.0, 4.0]"));
+}
+
+#[test]
+fn system_rejects_small_storage() {
+  let storage = DynamicStorage::new(SystemSize { r_out: 1, ..temperature_controller::SystemImpl::SIZE });
+  let result = temperature_controller::SystemImpl::new(&storage);
+  assert!(matches!(result, Err(Error::SizeMismatch { .. })));
+  assert!(storage.check_size(SystemSize { r_out: 1, ..SystemSize::new() }).is_ok());
+}